@@ -1,112 +1,272 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
+    extract::{rejection::JsonRejection, DefaultBodyLimit, Path, Query, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{Json, Response},
     routing::{get, post},
-    Router,
+    Extension, Router,
 };
 use tower::ServiceBuilder;
 use tower_http::cors::CorsLayer;
 use std::collections::HashMap;
-use std::sync::Arc;
-use uuid::Uuid;
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use anyhow::Result;
 
-use crate::models::{DeviceSignal, DeviceSignalRequest, ApiResponse};
-use crate::services::DataService;
+use crate::models::{
+    Aggregation, AggregationWindow, ApiResponse, BatchItemResult, BatchResult, DeviceSignal, DeviceSignalRequest,
+    ItemStatus, SignalQuery, SignalSeries,
+};
+use crate::services::SharedDataService;
+use crate::error::ApiError;
+use crate::auth::{self, ApiKey, Scope};
+use crate::dedup::InFlight;
+use crate::opcua_client;
+use crate::validation::{FieldError, ValidationErrors};
 
-pub type AppState = Arc<DataService>;
+pub type AppState = SharedDataService;
 
 pub fn create_router(state: AppState) -> Router {
-    Router::new()
-        // 设备信号相关接口
+    let body_limit = state.current().config().ingest.max_body_bytes;
+
+    // 设备信号写入接口
+    let write_routes = Router::new()
         .route("/api/signals", post(receive_signal))
         .route("/api/signals/batch", post(receive_batch_signals))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_write));
+
+    // 查询和状态接口
+    let read_routes = Router::new()
         .route("/api/signals/device/:device_id", get(get_device_signals))
         .route("/api/signals/latest", get(get_latest_signals))
-        
-        // 健康检查和状态接口
-        .route("/api/health", get(health_check))
+        .route("/api/signals/timeseries", get(get_timeseries_signals))
+        .route("/api/signals/query", get(query_signals))
         .route("/api/status", get(system_status))
-        
-        // 测试接口
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_read));
+
+    // 测试接口
+    let admin_routes = Router::new()
         .route("/api/test/kafka", post(test_kafka))
-        
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_admin));
+
+    Router::new()
+        // 健康检查不需要认证，供负载均衡和监控探测
+        .route("/api/health", get(health_check))
+        .merge(write_routes)
+        .merge(read_routes)
+        .merge(admin_routes)
         .layer(
             ServiceBuilder::new()
                 .layer(CorsLayer::permissive()) // 允许跨域访问
+                .layer(DefaultBodyLimit::max(body_limit))
         )
         .with_state(state)
 }
 
+async fn require_read(State(state): State<AppState>, request: Request, next: Next) -> Result<Response, ApiError> {
+    authorize(state, Scope::Read, request, next).await
+}
+
+async fn require_write(State(state): State<AppState>, request: Request, next: Next) -> Result<Response, ApiError> {
+    authorize(state, Scope::Write, request, next).await
+}
+
+async fn require_admin(State(state): State<AppState>, request: Request, next: Next) -> Result<Response, ApiError> {
+    authorize(state, Scope::Admin, request, next).await
+}
+
+/// 认证中间件，通过后把密钥信息放入请求扩展供处理函数检查设备绑定
+async fn authorize(state: AppState, scope: Scope, request: Request, next: Next) -> Result<Response, ApiError> {
+    let data_service = state.current();
+    if !data_service.authenticator().enabled() {
+        return Ok(next.run(request).await);
+    }
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let (mut request, key) = auth::authenticate(&data_service, request, scope).await.map_err(|e| {
+        log::warn!("Rejected {} {}: {}", method, path, e);
+        e
+    })?;
+
+    log::debug!("Authenticated {} {} with API key {}", method, path, key.id);
+    request.extensions_mut().insert(key);
+    Ok(next.run(request).await)
+}
+
+/// 绑定设备的密钥只能写入该设备的信号
+fn check_device(key: Option<&ApiKey>, device_id: &str) -> Result<(), FieldError> {
+    match key {
+        Some(key) if !key.allows_device(device_id) => {
+            Err(FieldError::new("device_id", "not permitted for this API key"))
+        },
+        _ => Ok(()),
+    }
+}
+
 /// 接收单个设备信号
+///
+/// 可通过请求体中的`id`或`Idempotency-Key`请求头提供幂等键，重试时不会重复写入。
+/// 相同ID的信号仍在写入时返回409，客户端稍后重试即可。
 async fn receive_signal(
-    State(data_service): State<AppState>,
-    Json(request): Json<DeviceSignalRequest>,
-) -> Result<Json<ApiResponse<String>>, StatusCode> {
+    State(state): State<AppState>,
+    key: Option<Extension<ApiKey>>,
+    headers: HeaderMap,
+    payload: Result<Json<DeviceSignalRequest>, JsonRejection>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let Json(mut request) = payload?;
+    if let Err(e) = check_device(key.as_deref(), &request.device_id) {
+        return Err(ApiError::Forbidden(format!("{}: {}", e.field, e.message)));
+    }
+    if request.id.is_none() {
+        request.id = idempotency_key(&headers);
+    }
+    let data_service = state.current();
     log::info!("Received signal from device: {} - {}", request.device_id, request.signal_type);
 
-    let signal = DeviceSignal {
-        id: Some(Uuid::new_v4()),
-        device_id: request.device_id,
-        signal_type: request.signal_type,
-        value: request.value,
-        unit: request.unit,
-        timestamp: Utc::now(),
-        metadata: request.metadata,
-    };
+    let signal = request.into_signal(&data_service.config().ingest).map_err(|errors| {
+        log::warn!("Rejected signal: {}", errors);
+        ApiError::from(errors)
+    })?;
 
     match data_service.process_signal(&signal).await {
         Ok(_) => {
             log::debug!("Signal processed successfully");
             Ok(Json(ApiResponse::success("Signal processed successfully".to_string())))
         },
+        Err(e) if e.is::<InFlight>() => {
+            log::warn!("Rejected concurrent retry: {}", e);
+            Err(ApiError::Conflict(e.to_string()))
+        },
         Err(e) => {
             log::error!("Failed to process signal: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(ApiError::ServiceUnavailable(format!("Failed to process signal: {}", e)))
         }
     }
 }
 
 /// 批量接收设备信号
+///
+/// 返回每个信号的处理结果：全部接收时为200，全部未通过校验为422，全部写入失败为503，
+/// 部分成功时为207，客户端只需重试`failed`的信号。
+/// 提供`Idempotency-Key`请求头时，未指定`id`的信号使用`{key}:{序号}`作为幂等键。
 async fn receive_batch_signals(
-    State(data_service): State<AppState>,
-    Json(requests): Json<Vec<DeviceSignalRequest>>,
-) -> Result<Json<ApiResponse<String>>, StatusCode> {
+    State(state): State<AppState>,
+    key: Option<Extension<ApiKey>>,
+    headers: HeaderMap,
+    payload: Result<Json<Vec<DeviceSignalRequest>>, JsonRejection>,
+) -> Result<(StatusCode, Json<ApiResponse<BatchResult>>), ApiError> {
+    let Json(requests) = payload?;
+    let batch_key = idempotency_key(&headers);
+    let data_service = state.current();
+    let ingest = &data_service.config().ingest;
     log::info!("Received batch of {} signals", requests.len());
 
-    let signals: Vec<DeviceSignal> = requests
-        .into_iter()
-        .map(|req| DeviceSignal {
-            id: Some(Uuid::new_v4()),
-            device_id: req.device_id,
-            signal_type: req.signal_type,
-            value: req.value,
-            unit: req.unit,
-            timestamp: Utc::now(),
-            metadata: req.metadata,
-        })
-        .collect();
-
-    match data_service.process_batch_signals(&signals).await {
-        Ok(_) => {
-            log::debug!("Batch signals processed successfully");
-            Ok(Json(ApiResponse::success(format!("Processed {} signals successfully", signals.len()))))
-        },
-        Err(e) => {
-            log::error!("Failed to process batch signals: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+    if requests.len() > ingest.max_batch_size {
+        return Err(ApiError::PayloadTooLarge(format!(
+            "Batch contains {} signals, maximum is {}",
+            requests.len(), ingest.max_batch_size
+        )));
+    }
+
+    let mut results = Vec::with_capacity(requests.len());
+    let mut signals = Vec::with_capacity(requests.len());
+    for (index, mut request) in requests.into_iter().enumerate() {
+        if request.id.is_none() {
+            request.id = batch_key.as_ref().map(|key| format!("{}:{}", key, index));
         }
+
+        let signal = check_device(key.as_deref(), &request.device_id)
+            .map_err(|e| ValidationErrors(vec![e]))
+            .and_then(|_| request.into_signal(ingest));
+
+        match signal {
+            Ok(signal) => {
+                results.push(BatchItemResult {
+                    index,
+                    id: signal.id,
+                    status: ItemStatus::Accepted,
+                    errors: Vec::new(),
+                    sink_errors: Default::default(),
+                    buffered: Vec::new(),
+                    duplicate: false,
+                    in_flight: false,
+                });
+                signals.push(signal);
+            },
+            Err(e) => results.push(BatchItemResult {
+                index,
+                id: None,
+                status: ItemStatus::Rejected,
+                errors: e.0,
+                sink_errors: Default::default(),
+                buffered: Vec::new(),
+                duplicate: false,
+                in_flight: false,
+            }),
+        }
+    }
+
+    // 写入结果按顺序对应通过校验的信号
+    let outcomes = data_service.process_batch_detailed(&signals).await;
+    let accepted_items = results.iter_mut().filter(|r| r.status == ItemStatus::Accepted);
+    for (result, outcome) in accepted_items.zip(outcomes) {
+        if !outcome.failed.is_empty() || outcome.in_flight {
+            result.status = ItemStatus::Failed;
+        }
+        result.sink_errors = outcome.failed;
+        result.buffered = outcome.buffered;
+        result.duplicate = outcome.duplicate;
+        result.in_flight = outcome.in_flight;
+    }
+
+    let count = |status: ItemStatus| results.iter().filter(|r| r.status == status).count();
+    let batch = BatchResult {
+        accepted: count(ItemStatus::Accepted),
+        rejected: count(ItemStatus::Rejected),
+        failed: count(ItemStatus::Failed),
+        results,
+    };
+    let total = batch.results.len();
+
+    let status = if batch.accepted == total {
+        StatusCode::OK
+    } else if batch.rejected == total {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else if batch.failed == total {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::MULTI_STATUS
+    };
+
+    if status == StatusCode::OK {
+        log::debug!("Batch signals processed successfully");
+        return Ok((status, Json(ApiResponse::success(batch))));
     }
+
+    log::warn!(
+        "Batch partially processed: {} accepted, {} rejected, {} failed",
+        batch.accepted, batch.rejected, batch.failed
+    );
+    let message = format!("Accepted {} of {} signals", batch.accepted, total);
+    Ok((status, Json(ApiResponse::partial(message, batch))))
+}
+
+/// 读取`Idempotency-Key`请求头
+fn idempotency_key(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("idempotency-key")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
 }
 
 /// 获取指定设备的信号历史
 async fn get_device_signals(
-    State(data_service): State<AppState>,
+    State(state): State<AppState>,
     Path(device_id): Path<String>,
     Query(params): Query<HashMap<String, String>>,
-) -> Result<Json<ApiResponse<Vec<DeviceSignal>>>, StatusCode> {
+) -> Result<Json<ApiResponse<Vec<DeviceSignal>>>, ApiError> {
+    let data_service = state.current();
     let limit = params.get("limit")
         .and_then(|l| l.parse::<i32>().ok())
         .unwrap_or(100);
@@ -118,16 +278,17 @@ async fn get_device_signals(
         },
         Err(e) => {
             log::error!("Failed to get device signals: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(ApiError::Internal(format!("Failed to get device signals: {}", e)))
         }
     }
 }
 
 /// 获取最新的信号数据
 async fn get_latest_signals(
-    State(data_service): State<AppState>,
+    State(state): State<AppState>,
     Query(params): Query<HashMap<String, String>>,
-) -> Result<Json<ApiResponse<Vec<DeviceSignal>>>, StatusCode> {
+) -> Result<Json<ApiResponse<Vec<DeviceSignal>>>, ApiError> {
+    let data_service = state.current();
     let limit = params.get("limit")
         .and_then(|l| l.parse::<i32>().ok())
         .unwrap_or(50);
@@ -139,60 +300,165 @@ async fn get_latest_signals(
         },
         Err(e) => {
             log::error!("Failed to get latest signals: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(ApiError::Internal(format!("Failed to get latest signals: {}", e)))
         }
     }
 }
 
-/// 健康检查接口
-async fn health_check(
-    State(data_service): State<AppState>,
-) -> Result<Json<ApiResponse<HashMap<String, bool>>>, StatusCode> {
-    let mut status = HashMap::new();
-    
-    // 检查MariaDB连接
-    match data_service.mariadb_health_check().await {
-        Ok(healthy) => status.insert("mariadb".to_string(), healthy),
-        Err(_) => status.insert("mariadb".to_string(), false),
-    };
+/// 从InfluxDB获取最近的信号数据
+async fn get_timeseries_signals(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<ApiResponse<Vec<DeviceSignal>>>, ApiError> {
+    let data_service = state.current();
+    let device_id = params.get("device_id").map(|d| d.as_str());
+    let limit = params.get("limit")
+        .and_then(|l| l.parse::<u32>().ok())
+        .unwrap_or(100);
 
-    // 检查InfluxDB连接
-    match data_service.influxdb_health_check().await {
-        Ok(healthy) => status.insert("influxdb".to_string(), healthy),
-        Err(_) => status.insert("influxdb".to_string(), false),
+    match data_service.query_recent_signals(device_id, Some(limit)).await {
+        Ok(signals) => {
+            log::debug!("Retrieved {} signals from InfluxDB", signals.len());
+            Ok(Json(ApiResponse::success(signals)))
+        },
+        Err(e) => {
+            log::error!("Failed to query InfluxDB signals: {}", e);
+            Err(ApiError::Internal(format!("Failed to query InfluxDB signals: {}", e)))
+        }
+    }
+}
+
+/// 按时间范围查询信号，支持按时间窗口聚合
+///
+/// 参数: device_id、signal_type（逗号分隔多个值）、from/to（RFC3339，默认最近1小时）、
+/// aggregation（mean/min/max/last）与interval（1m/5m/1h）、limit（每个序列的最大点数）、source（influxdb/mariadb）
+async fn query_signals(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<ApiResponse<Vec<SignalSeries>>>, ApiError> {
+    let data_service = state.current();
+
+    let query = parse_signal_query(&params).map_err(|message| {
+        log::warn!("Invalid signal query: {}", message);
+        ApiError::BadRequest(message)
+    })?;
+
+    match data_service.query_signals(&query, params.get("source").map(|s| s.as_str())).await {
+        Ok(series) => {
+            log::debug!("Signal query returned {} series", series.len());
+            Ok(Json(ApiResponse::success(series)))
+        },
+        Err(e) => {
+            log::error!("Failed to query signals: {}", e);
+            Err(ApiError::Internal(format!("Failed to query signals: {}", e)))
+        }
+    }
+}
+
+/// 解析时间范围查询参数
+fn parse_signal_query(params: &HashMap<String, String>) -> Result<SignalQuery, String> {
+    let list = |key: &str| -> Vec<String> {
+        params.get(key)
+            .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default()
     };
+    let time = |key: &str| -> Result<Option<DateTime<Utc>>, String> {
+        match params.get(key) {
+            Some(value) => DateTime::parse_from_rfc3339(value)
+                .map(|t| Some(t.with_timezone(&Utc)))
+                .map_err(|e| format!("Invalid '{}' timestamp: {}", key, e)),
+            None => Ok(None),
+        }
+    };
+
+    let to = time("to")?.unwrap_or_else(Utc::now);
+    let from = time("from")?.unwrap_or(to - Duration::hours(1));
+    if from >= to {
+        return Err("'from' must be earlier than 'to'".to_string());
+    }
 
-    // 检查Kafka连接
-    match data_service.kafka_health_check().await {
-        Ok(healthy) => status.insert("kafka".to_string(), healthy),
-        Err(_) => status.insert("kafka".to_string(), false),
+    let aggregation = match params.get("aggregation") {
+        Some(name) => {
+            let aggregation = Aggregation::parse(name)
+                .ok_or_else(|| format!("Unsupported aggregation '{}', expected mean, min, max or last", name))?;
+            let interval = params.get("interval").map(|i| i.as_str()).unwrap_or("1m");
+            let window = AggregationWindow::parse(interval)
+                .ok_or_else(|| format!("Unsupported interval '{}', expected 1m, 5m or 1h", interval))?;
+            Some((aggregation, window))
+        },
+        None => None,
     };
 
+    let limit = params.get("limit")
+        .and_then(|l| l.parse::<u32>().ok())
+        .unwrap_or(10_000);
+
+    Ok(SignalQuery {
+        device_ids: list("device_id"),
+        signal_types: list("signal_type"),
+        from,
+        to,
+        aggregation,
+        limit,
+    })
+}
+
+/// 健康检查接口
+async fn health_check(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<HashMap<String, bool>>>, ApiError> {
+    let data_service = state.current();
+    let status = data_service.health_status().await;
+
     let all_healthy = status.values().all(|&h| h);
     
     if all_healthy {
         Ok(Json(ApiResponse::success(status)))
     } else {
         log::warn!("Health check failed: {:?}", status);
-        Err(StatusCode::SERVICE_UNAVAILABLE)
+        let unhealthy: Vec<&str> = status
+            .iter()
+            .filter(|(_, healthy)| !**healthy)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        Err(ApiError::ServiceUnavailable(format!("Unhealthy services: {}", unhealthy.join(", "))))
     }
 }
 
 /// 系统状态接口
-async fn system_status() -> Json<ApiResponse<HashMap<String, String>>> {
+async fn system_status(
+    State(state): State<AppState>,
+) -> Json<ApiResponse<HashMap<String, String>>> {
+    let data_service = state.current();
     let mut status = HashMap::new();
     status.insert("service".to_string(), "Device Signal Gateway".to_string());
     status.insert("version".to_string(), "1.0.0".to_string());
     status.insert("uptime".to_string(), format!("{:?}", std::time::SystemTime::now()));
+
+    // 外部服务连接状态
+    for (name, connection) in data_service.connection_status() {
+        status.insert(format!("{}_connection", name), format!("{:?}", connection));
+    }
+
+    // 本地缓冲中待投递的信号数量
+    for (name, count) in data_service.buffered_counts().await {
+        status.insert(format!("{}_buffered", name), count.to_string());
+    }
+
+    // OPC UA写入来不及处理时转存到本地缓冲和丢弃的信号数量
+    status.insert("opcua_spilled".to_string(), opcua_client::spilled_signals().to_string());
+    status.insert("opcua_dropped".to_string(), opcua_client::dropped_signals().to_string());
     
     Json(ApiResponse::success(status))
 }
 
 /// 测试Kafka消息发送
 async fn test_kafka(
-    State(data_service): State<AppState>,
-    Json(payload): Json<HashMap<String, String>>,
-) -> Result<Json<ApiResponse<String>>, StatusCode> {
+    State(state): State<AppState>,
+    payload: Result<Json<HashMap<String, String>>, JsonRejection>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let Json(payload) = payload?;
+    let data_service = state.current();
     let key = payload.get("key").unwrap_or(&"test".to_string()).clone();
     let message = payload.get("message").unwrap_or(&"test message".to_string()).clone();
 
@@ -203,7 +469,7 @@ async fn test_kafka(
         },
         Err(e) => {
             log::error!("Failed to send test message: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(ApiError::Internal(format!("Failed to send test message: {}", e)))
         }
     }
 }
\ No newline at end of file
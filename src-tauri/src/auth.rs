@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, Weak};
+use std::time::{Duration, Instant};
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::HeaderMap,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use crate::config::AuthConfig;
+use crate::error::ApiError;
+use crate::services::DataService;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 时间窗口内已使用的签名及其时间戳
+type SignatureLog = Mutex<HashMap<String, i64>>;
+
+/// 进程内共享的签名记录，热重载前后的服务使用同一份记录，重载后也不能重放已使用的签名
+static SHARED_SIGNATURES: OnceLock<Mutex<Weak<SignatureLog>>> = OnceLock::new();
+
+/// 接口权限范围，`admin`包含全部权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Read,
+    Write,
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+            Scope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "read" => Some(Scope::Read),
+            "write" => Some(Scope::Write),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// API密钥信息，不包含密钥本身
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    /// 绑定的设备，设置后只能写入该设备的信号
+    pub device_id: Option<String>,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.revoked_at.is_none()
+            && self.scopes.iter().any(|granted| *granted == Scope::Admin || *granted == scope)
+    }
+
+    /// 是否可以写入指定设备的信号
+    pub fn allows_device(&self, device_id: &str) -> bool {
+        self.device_id.as_deref().map_or(true, |bound| bound == device_id)
+    }
+}
+
+/// 新建的API密钥，`token`只在创建时返回一次
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedApiKey {
+    pub key: ApiKey,
+    pub token: String,
+}
+
+/// MariaDB中保存的密钥记录
+///
+/// 令牌认证只需要密钥的SHA-256摘要；签名认证需要密钥本身，数据库中保存用本地密钥
+/// （见`secrets::encrypt`）加密的副本，只读取数据库无法伪造签名。
+#[derive(Debug, Clone)]
+pub struct StoredApiKey {
+    pub key: ApiKey,
+    pub secret_hash: String,
+    /// 解密后的签名密钥，早期创建的密钥没有保存，只能使用令牌认证
+    pub signing_secret: Option<String>,
+}
+
+impl StoredApiKey {
+    /// 生成新密钥，返回记录和令牌，令牌格式为`{id}.{secret}`
+    pub fn generate(name: String, device_id: Option<String>, scopes: Vec<Scope>) -> (Self, String) {
+        let id = Uuid::new_v4().simple().to_string()[..16].to_string();
+        let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let token = format!("{}.{}", id, secret);
+
+        let stored = Self {
+            key: ApiKey {
+                id,
+                name,
+                device_id,
+                scopes,
+                created_at: Utc::now(),
+                revoked_at: None,
+            },
+            secret_hash: hash_secret(&secret),
+            signing_secret: Some(secret),
+        };
+
+        (stored, token)
+    }
+
+    fn verify_secret(&self, secret: &str) -> bool {
+        constant_time_eq(hash_secret(secret).as_bytes(), self.secret_hash.as_bytes())
+    }
+
+    /// 校验请求签名，签名密钥为令牌中的`secret`部分
+    fn verify_signature(&self, message: &[u8], signature: &str) -> bool {
+        let secret = match &self.signing_secret {
+            Some(secret) => secret,
+            None => return false,
+        };
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(message);
+        let expected = format!("{:x}", mac.finalize().into_bytes());
+
+        constant_time_eq(expected.as_bytes(), signature.trim().to_ascii_lowercase().as_bytes())
+    }
+}
+
+/// 请求携带的认证信息
+enum Credentials {
+    /// `Authorization: Bearer {id}.{secret}`或`X-API-Key: {id}.{secret}`
+    Token { key_id: String, secret: String },
+    /// `X-Key-Id`、`X-Timestamp`和`X-Signature`请求头
+    Signature { key_id: String, timestamp: i64, signature: String },
+}
+
+impl Credentials {
+    fn from_headers(headers: &HeaderMap) -> Result<Self, ApiError> {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        if let Some(signature) = header("x-signature") {
+            let key_id = header("x-key-id")
+                .ok_or_else(|| ApiError::Unauthorized("Missing X-Key-Id header".to_string()))?;
+            let timestamp = header("x-timestamp")
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| ApiError::Unauthorized("Missing or invalid X-Timestamp header".to_string()))?;
+            return Ok(Credentials::Signature { key_id, timestamp, signature });
+        }
+
+        let token = header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer ").map(|token| token.trim().to_string()))
+            .or_else(|| header("x-api-key"))
+            .ok_or_else(|| ApiError::Unauthorized("Missing API key".to_string()))?;
+
+        match token.split_once('.') {
+            Some((key_id, secret)) if !key_id.is_empty() && !secret.is_empty() => Ok(Credentials::Token {
+                key_id: key_id.to_string(),
+                secret: secret.to_string(),
+            }),
+            _ => Err(ApiError::Unauthorized("Malformed API key".to_string())),
+        }
+    }
+}
+
+/// 密钥查询缓存和签名防重放记录
+pub struct Authenticator {
+    config: AuthConfig,
+    /// 按密钥ID缓存的密钥，不存在的ID不缓存，避免随意构造的ID占满内存
+    ///
+    /// 过期的记录仍然保留，密钥库不可用时在`offline_key_max_age_secs`内用于认证。
+    keys: Mutex<HashMap<String, (StoredApiKey, Instant)>>,
+    signatures: Arc<SignatureLog>,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Self {
+        Self {
+            config: config.clone(),
+            keys: Mutex::new(HashMap::new()),
+            signatures: shared_signatures(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// 缓存的密钥，未缓存或已过期时返回None
+    pub fn cached(&self, key_id: &str) -> Option<StoredApiKey> {
+        let ttl = Duration::from_secs(self.config.cache_ttl_secs);
+        match lock(&self.keys).get(key_id) {
+            Some((key, cached_at)) if cached_at.elapsed() < ttl => Some(key.clone()),
+            _ => None,
+        }
+    }
+
+    /// 最近一次成功查询到的密钥，只在密钥库不可用时使用
+    ///
+    /// 查询时间超过`offline_key_max_age_secs`的密钥可能已在其他实例或数据库中被吊销，不再使用。
+    pub fn last_known(&self, key_id: &str) -> Option<StoredApiKey> {
+        let max_age = Duration::from_secs(self.config.offline_key_max_age_secs);
+        match lock(&self.keys).get(key_id) {
+            Some((key, cached_at)) if cached_at.elapsed() < max_age => Some(key.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn remember(&self, key: &StoredApiKey) {
+        lock(&self.keys).insert(key.key.id.clone(), (key.clone(), Instant::now()));
+    }
+
+    /// 密钥创建或吊销后立即失效缓存
+    pub fn invalidate(&self, key_id: &str) {
+        lock(&self.keys).remove(key_id);
+    }
+
+    /// 检查签名时间戳，并拒绝时间窗口内重复使用的签名
+    fn check_replay(&self, timestamp: i64, signature: &str) -> Result<(), ApiError> {
+        let now = Utc::now().timestamp();
+        let window = self.config.max_clock_skew_secs;
+        if now.abs_diff(timestamp) > window {
+            return Err(ApiError::Unauthorized(format!(
+                "Request timestamp is outside the allowed window of {}s",
+                window
+            )));
+        }
+
+        let mut signatures = lock(&self.signatures);
+        signatures.retain(|_, used_at| now.abs_diff(*used_at) <= window);
+        if signatures.insert(signature.to_ascii_lowercase(), timestamp).is_some() {
+            return Err(ApiError::Unauthorized("Request signature has already been used".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// 认证请求并检查权限范围，返回（可能已重建的）请求和对应的密钥
+///
+/// 签名请求需要读取完整请求体来校验签名，校验后用读取的内容重建请求。
+pub async fn authenticate(
+    service: &DataService,
+    request: Request,
+    scope: Scope,
+) -> Result<(Request, ApiKey), ApiError> {
+    let credentials = Credentials::from_headers(request.headers())?;
+
+    let (request, stored) = match credentials {
+        Credentials::Token { key_id, secret } => {
+            let stored = lookup(service, &key_id).await?;
+            if !stored.verify_secret(&secret) {
+                return Err(ApiError::Unauthorized("Invalid API key".to_string()));
+            }
+            (request, stored)
+        },
+        Credentials::Signature { key_id, timestamp, signature } => {
+            let stored = lookup(service, &key_id).await?;
+
+            let (parts, body) = request.into_parts();
+            let body = to_bytes(body, service.config().ingest.max_body_bytes)
+                .await
+                .map_err(|e| ApiError::PayloadTooLarge(format!("Failed to read request body: {}", e)))?;
+
+            let path = parts.uri.path_and_query().map(|path| path.as_str()).unwrap_or("/");
+            let message = format!(
+                "{}\n{}\n{}\n{:x}",
+                parts.method,
+                path,
+                timestamp,
+                Sha256::digest(&body)
+            );
+            if !stored.verify_signature(message.as_bytes(), &signature) {
+                return Err(ApiError::Unauthorized("Invalid request signature".to_string()));
+            }
+            service.authenticator().check_replay(timestamp, &signature)?;
+
+            (Request::from_parts(parts, Body::from(body)), stored)
+        }
+    };
+
+    if stored.key.revoked_at.is_some() {
+        return Err(ApiError::Unauthorized("API key has been revoked".to_string()));
+    }
+    if !stored.key.allows(scope) {
+        return Err(ApiError::Forbidden(format!("API key lacks the '{}' scope", scope.as_str())));
+    }
+
+    Ok((request, stored.key))
+}
+
+/// 获取（或创建）进程内共享的签名记录
+fn shared_signatures() -> Arc<SignatureLog> {
+    let mut registry = lock(SHARED_SIGNATURES.get_or_init(|| Mutex::new(Weak::new())));
+    if let Some(signatures) = registry.upgrade() {
+        return signatures;
+    }
+
+    let signatures = Arc::new(Mutex::new(HashMap::new()));
+    *registry = Arc::downgrade(&signatures);
+    signatures
+}
+
+async fn lookup(service: &DataService, key_id: &str) -> Result<StoredApiKey, ApiError> {
+    match service.api_key(key_id).await {
+        Ok(Some(stored)) => Ok(stored),
+        Ok(None) => Err(ApiError::Unauthorized("Invalid API key".to_string())),
+        Err(e) => {
+            log::error!("Failed to look up API key: {}", e);
+            Err(ApiError::ServiceUnavailable(format!("API key store unavailable: {}", e)))
+        }
+    }
+}
+
+/// 密钥的SHA-256摘要（十六进制）
+pub fn hash_secret(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+/// 比较耗时与内容无关，避免通过响应时间猜测密钥
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AuthConfig {
+        AuthConfig {
+            enabled: true,
+            max_clock_skew_secs: 300,
+            cache_ttl_secs: 60,
+            offline_key_max_age_secs: 900,
+        }
+    }
+
+    #[test]
+    fn signatures_cannot_be_replayed_after_reload() {
+        let now = Utc::now().timestamp();
+        let before = Authenticator::new(&config());
+        assert!(before.check_replay(now, "a1b2c3-reload").is_ok());
+
+        // 热重载时新服务在旧服务释放前创建
+        let after = Authenticator::new(&config());
+        drop(before);
+        assert!(after.check_replay(now, "A1B2C3-RELOAD").is_err());
+    }
+
+    #[test]
+    fn last_known_keys_expire_after_max_age() {
+        let (key, _) = StoredApiKey::generate("gateway".to_string(), None, vec![Scope::Write]);
+        let id = key.key.id.clone();
+
+        let auth = Authenticator::new(&config());
+        auth.remember(&key);
+        assert!(auth.last_known(&id).is_some());
+
+        let auth = Authenticator::new(&AuthConfig { offline_key_max_age_secs: 0, ..config() });
+        auth.remember(&key);
+        assert!(auth.last_known(&id).is_none());
+    }
+
+    #[test]
+    fn rejects_timestamps_outside_window() {
+        let auth = Authenticator::new(&config());
+        let now = Utc::now().timestamp();
+
+        assert!(auth.check_replay(now - 301, "stale-signature").is_err());
+        assert!(auth.check_replay(now + 301, "future-signature").is_err());
+        assert!(auth.check_replay(now - 299, "fresh-signature").is_ok());
+    }
+}
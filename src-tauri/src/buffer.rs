@@ -0,0 +1,494 @@
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use tokio::io::AsyncWriteExt;
+use crate::models::DeviceSignal;
+use crate::config::BufferConfig;
+use crate::sink;
+
+/// 同一缓冲文件在进程内只对应一个实例，热重载前后的服务共享同一个队列
+static OPEN_BUFFERS: OnceLock<Mutex<HashMap<PathBuf, Weak<SignalBuffer>>>> = OnceLock::new();
+
+/// 缓冲文件中的一条记录
+#[derive(Debug, Serialize, Deserialize)]
+struct BufferedSignal {
+    buffered_at: DateTime<Utc>,
+    signal: DeviceSignal,
+}
+
+/// 死信文件中的一条记录，保存被sink拒绝的信号和拒绝原因
+#[derive(Debug, Serialize)]
+struct RejectedSignal<'a> {
+    rejected_at: DateTime<Utc>,
+    error: String,
+    signal: &'a DeviceSignal,
+}
+
+struct BufferState {
+    entries: usize,
+}
+
+/// 单个sink的本地预写队列
+///
+/// 写入失败的信号以JSON行的形式追加到磁盘文件，sink恢复后按写入顺序重放。
+/// 队列非空时新信号也进入队列，保证重放后数据顺序不变。
+/// 被sink拒绝的信号移入`{sink}.rejected.jsonl`，不会阻塞后面的数据。
+pub struct SignalBuffer {
+    sink: &'static str,
+    path: PathBuf,
+    rejected_path: PathBuf,
+    state: tokio::sync::Mutex<BufferState>,
+    /// 队列限制，热重载时直接更新，不需要等待队列锁
+    max_entries: AtomicUsize,
+    retention_hours: AtomicU64,
+    /// 重放期间持有，同一队列同时只有一个重放任务，且重放期间不压缩文件
+    replay: tokio::sync::Mutex<()>,
+}
+
+impl SignalBuffer {
+    /// 打开（或复用已打开的）sink缓冲队列
+    pub fn open(dir: &Path, sink: &'static str, config: &BufferConfig) -> Result<Arc<Self>> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.jsonl", sink.to_lowercase()));
+        let rejected_path = dir.join(format!("{}.rejected.jsonl", sink.to_lowercase()));
+
+        let registry = OPEN_BUFFERS.get_or_init(|| Mutex::new(HashMap::new()));
+        let mut registry = match registry.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if let Some(buffer) = registry.get(&path).and_then(Weak::upgrade) {
+            // 复用已有队列，仅更新限制
+            buffer.max_entries.store(config.max_entries, Ordering::Relaxed);
+            buffer.retention_hours.store(config.retention_hours, Ordering::Relaxed);
+            return Ok(buffer);
+        }
+
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(content) => content.lines().filter(|line| !line.trim().is_empty()).count(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+
+        if entries > 0 {
+            log::info!("{} buffer contains {} undelivered signals", sink, entries);
+        }
+
+        let buffer = Arc::new(Self {
+            sink,
+            path: path.clone(),
+            rejected_path,
+            state: tokio::sync::Mutex::new(BufferState { entries }),
+            max_entries: AtomicUsize::new(config.max_entries),
+            retention_hours: AtomicU64::new(config.retention_hours),
+            replay: tokio::sync::Mutex::new(()),
+        });
+        registry.insert(path, Arc::downgrade(&buffer));
+
+        Ok(buffer)
+    }
+
+    /// 所属sink名称
+    pub fn sink(&self) -> &'static str {
+        self.sink
+    }
+
+    fn max_entries(&self) -> usize {
+        self.max_entries.load(Ordering::Relaxed)
+    }
+
+    /// 缓冲的信号数量
+    pub async fn len(&self) -> usize {
+        self.state.lock().await.entries
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// 将信号追加到队列末尾
+    ///
+    /// NaN和无穷大在JSON中无法表示，包含这类值的信号不会写入队列。
+    pub async fn push(&self, signals: &[DeviceSignal]) -> Result<()> {
+        if signals.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(signal) = signals.iter().find(|signal| !signal.is_finite()) {
+            return Err(anyhow::anyhow!(
+                "Signal {}/{} has a non-finite value and cannot be buffered",
+                signal.device_id, signal.signal_type
+            ));
+        }
+
+        let mut state = self.state.lock().await;
+        let now = Utc::now();
+
+        let mut lines = String::new();
+        for signal in signals {
+            let record = BufferedSignal {
+                buffered_at: now,
+                signal: signal.clone(),
+            };
+            lines.push_str(&serde_json::to_string(&record)?);
+            lines.push('\n');
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(lines.as_bytes()).await?;
+        file.sync_data().await?;
+
+        state.entries += signals.len();
+        log::debug!("Buffered {} signals for {} ({} pending)", signals.len(), self.sink, state.entries);
+
+        // 重放期间文件只追加，重放结束后再压缩
+        if state.entries > self.max_entries() && self.replay.try_lock().is_ok() {
+            self.compact(&mut state).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 按顺序重放缓冲的信号，返回成功投递的数量
+    ///
+    /// 只在读取和截断文件时持有队列锁，投递期间写入和状态查询不受影响。
+    /// 某一批投递失败时停止重放，剩余信号保留在队列中等待下次重放；
+    /// 被sink拒绝的批次逐步二分，找出的信号移入死信文件。
+    pub async fn replay<F, Fut>(&self, batch_size: usize, deliver: F) -> Result<usize>
+    where
+        F: Fn(Vec<DeviceSignal>) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let _replay = self.replay.lock().await;
+
+        let (lines, records) = {
+            let state = self.state.lock().await;
+            if state.entries == 0 {
+                return Ok(0);
+            }
+            let lines = self.read_lines().await?;
+            let records = self.parse_records(&lines);
+            (lines, records)
+        };
+
+        let total = records.len();
+        let mut pending: VecDeque<&[(usize, BufferedSignal)]> = records.chunks(batch_size.max(1)).collect();
+        // 已投递或移入死信文件的记录数，总是从队首开始的连续前缀
+        let mut consumed = 0;
+        let mut delivered = 0;
+        let mut failure = None;
+
+        while let Some(chunk) = pending.pop_front() {
+            let signals = chunk.iter().map(|(_, record)| record.signal.clone()).collect();
+            match deliver(signals).await {
+                Ok(_) => {
+                    consumed += chunk.len();
+                    delivered += chunk.len();
+                },
+                Err(e) if sink::is_rejected(&e) && chunk.len() > 1 => {
+                    let (front, back) = chunk.split_at(chunk.len() / 2);
+                    pending.push_front(back);
+                    pending.push_front(front);
+                },
+                Err(e) if sink::is_rejected(&e) => {
+                    let signal = &chunk[0].1.signal;
+                    log::error!(
+                        "{} rejected buffered signal {}/{}, moving it to {}: {}",
+                        self.sink, signal.device_id, signal.signal_type, self.rejected_path.display(), e
+                    );
+                    self.reject(signal, &e).await?;
+                    consumed += 1;
+                },
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                },
+            }
+        }
+
+        // 截断时包括已处理记录之前的过期和损坏行
+        let consumed_lines = records.get(consumed).map_or(lines.len(), |(line, _)| *line);
+        if consumed_lines > 0 {
+            let mut state = self.state.lock().await;
+            let current = self.read_lines().await?;
+            self.rewrite(&mut state, &current[consumed_lines.min(current.len())..]).await?;
+            if state.entries > self.max_entries() {
+                self.compact(&mut state).await?;
+            }
+        }
+
+        if delivered > 0 {
+            log::info!("Replayed {}/{} buffered signals to {}", delivered, total, self.sink);
+        }
+
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(delivered),
+        }
+    }
+
+    /// 追加到死信文件
+    async fn reject(&self, signal: &DeviceSignal, error: &anyhow::Error) -> Result<()> {
+        let record = RejectedSignal {
+            rejected_at: Utc::now(),
+            error: error.to_string(),
+            signal,
+        };
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.rejected_path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    /// 丢弃过期和超出容量的最旧数据
+    async fn compact(&self, state: &mut BufferState) -> Result<()> {
+        let lines = self.read_lines().await?;
+        let records = self.parse_records(&lines);
+
+        // 留出10%余量，避免每次写入都重写文件
+        let max_entries = self.max_entries();
+        let target = max_entries - max_entries / 10;
+        let skip = records.len().saturating_sub(target);
+        if skip > 0 {
+            log::warn!("{} buffer full, dropping {} oldest signals", self.sink, skip);
+        }
+
+        let kept: Vec<String> = records[skip..].iter().map(|(line, _)| lines[*line].clone()).collect();
+        self.rewrite(state, &kept).await
+    }
+
+    /// 读取缓冲文件的非空行
+    async fn read_lines(&self) -> Result<Vec<String>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => Ok(content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(str::to_string)
+                .collect()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 解析未过期的缓冲记录及其所在行号
+    fn parse_records(&self, lines: &[String]) -> Vec<(usize, BufferedSignal)> {
+        let retention_hours = self.retention_hours.load(Ordering::Relaxed);
+        let cutoff = Utc::now() - chrono::Duration::hours(retention_hours as i64);
+        let mut records = Vec::new();
+        let mut expired = 0;
+
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_str::<BufferedSignal>(line) {
+                Ok(record) if record.buffered_at < cutoff => expired += 1,
+                Ok(record) => records.push((index, record)),
+                Err(e) => log::warn!("Skipping corrupt {} buffer entry: {}", self.sink, e),
+            }
+        }
+
+        if expired > 0 {
+            log::warn!("Dropped {} expired signals from {} buffer", expired, self.sink);
+        }
+
+        records
+    }
+
+    /// 用给定的行替换缓冲文件内容
+    async fn rewrite(&self, state: &mut BufferState, lines: &[String]) -> Result<()> {
+        let mut content = String::new();
+        for line in lines {
+            content.push_str(line);
+            content.push('\n');
+        }
+
+        // 先写临时文件再替换，避免中途崩溃损坏队列
+        let tmp_path = self.path.with_extension("jsonl.tmp");
+        let mut file = tokio::fs::File::create(&tmp_path).await?;
+        file.write_all(content.as_bytes()).await?;
+        file.sync_data().await?;
+        drop(file);
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+
+        state.entries = lines.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::sink::Rejected;
+
+    fn config(max_entries: usize) -> BufferConfig {
+        BufferConfig {
+            enabled: true,
+            directory: None,
+            max_entries,
+            retention_hours: 24,
+            replay_interval_secs: 1,
+            replay_batch_size: 2,
+        }
+    }
+
+    fn signal(index: usize) -> DeviceSignal {
+        DeviceSignal {
+            id: None,
+            idempotency_key: None,
+            device_id: "pump-1".to_string(),
+            signal_type: format!("s{}", index),
+            value: Some((index as f64).into()),
+            fields: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            unit: None,
+            timestamp: Utc::now(),
+            received_at: None,
+            metadata: None,
+        }
+    }
+
+    fn signals(range: std::ops::Range<usize>) -> Vec<DeviceSignal> {
+        range.map(signal).collect()
+    }
+
+    fn types(signals: &[DeviceSignal]) -> Vec<String> {
+        signals.iter().map(|signal| signal.signal_type.clone()).collect()
+    }
+
+    fn expected(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|index| format!("s{}", index)).collect()
+    }
+
+    /// 投递成功并记录收到的信号
+    async fn collect(buffer: &SignalBuffer, batch_size: usize) -> Vec<String> {
+        let delivered = Mutex::new(Vec::new());
+        buffer.replay(batch_size, |signals| {
+            delivered.lock().unwrap().extend(types(&signals));
+            async { Ok(()) }
+        }).await.unwrap();
+        delivered.into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn replays_in_order_after_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let buffer = SignalBuffer::open(dir.path(), "Test", &config(100)).unwrap();
+        buffer.push(&signals(0..5)).await.unwrap();
+
+        let calls = AtomicUsize::new(0);
+        let delivered = Mutex::new(Vec::new());
+        let result = buffer.replay(2, |signals| {
+            let failed = calls.fetch_add(1, Ordering::SeqCst) == 1;
+            if !failed {
+                delivered.lock().unwrap().extend(types(&signals));
+            }
+            async move {
+                if failed {
+                    return Err(anyhow::anyhow!("connection refused"));
+                }
+                Ok(())
+            }
+        }).await;
+
+        assert!(result.is_err());
+        assert_eq!(delivered.into_inner().unwrap(), expected(0..2));
+        assert_eq!(buffer.len().await, 3);
+
+        assert_eq!(collect(&buffer, 2).await, expected(2..5));
+        assert!(buffer.is_empty().await);
+    }
+
+    #[tokio::test]
+    async fn signals_pushed_during_delivery_survive_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let buffer = SignalBuffer::open(dir.path(), "Test", &config(100)).unwrap();
+        buffer.push(&signals(0..3)).await.unwrap();
+
+        let pushed = AtomicUsize::new(100);
+        let delivered = buffer.replay(1, |_| {
+            let signal = signal(pushed.fetch_add(1, Ordering::SeqCst));
+            let buffer = buffer.clone();
+            async move { buffer.push(&[signal]).await }
+        }).await.unwrap();
+
+        assert_eq!(delivered, 3);
+        assert_eq!(buffer.len().await, 3);
+        assert_eq!(collect(&buffer, 10).await, expected(100..103));
+    }
+
+    #[tokio::test]
+    async fn rejected_signal_moves_to_dead_letter_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let buffer = SignalBuffer::open(dir.path(), "Test", &config(100)).unwrap();
+        buffer.push(&signals(0..5)).await.unwrap();
+
+        let delivered = Mutex::new(Vec::new());
+        let count = buffer.replay(4, |signals| {
+            let types = types(&signals);
+            let rejected = types.iter().any(|signal_type| signal_type == "s2");
+            if !rejected {
+                delivered.lock().unwrap().extend(types);
+            }
+            async move {
+                if rejected {
+                    return Err(anyhow::Error::new(Rejected("field type conflict".to_string())));
+                }
+                Ok(())
+            }
+        }).await.unwrap();
+
+        assert_eq!(count, 4);
+        assert_eq!(delivered.into_inner().unwrap(), vec!["s0", "s1", "s3", "s4"]);
+        assert!(buffer.is_empty().await);
+
+        let dead_letters = std::fs::read_to_string(dir.path().join("test.rejected.jsonl")).unwrap();
+        let lines: Vec<serde_json::Value> = dead_letters.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["signal"]["signal_type"], "s2");
+        assert_eq!(lines[0]["error"], "field type conflict");
+    }
+
+    #[tokio::test]
+    async fn compaction_keeps_newest_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let buffer = SignalBuffer::open(dir.path(), "Test", &config(10)).unwrap();
+
+        for index in 0..11 {
+            buffer.push(&[signal(index)]).await.unwrap();
+        }
+
+        // 超出容量后压缩到90%
+        assert_eq!(buffer.len().await, 9);
+        assert_eq!(collect(&buffer, 100).await, expected(2..11));
+    }
+
+    #[tokio::test]
+    async fn reopening_updates_limits_while_queue_is_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let buffer = SignalBuffer::open(dir.path(), "Test", &config(100)).unwrap();
+
+        let state = buffer.state.lock().await;
+        let reopened = SignalBuffer::open(dir.path(), "Test", &BufferConfig { retention_hours: 1, ..config(10) }).unwrap();
+        drop(state);
+
+        assert!(Arc::ptr_eq(&buffer, &reopened));
+        assert_eq!(buffer.max_entries(), 10);
+        assert_eq!(buffer.retention_hours.load(Ordering::Relaxed), 1);
+    }
+}
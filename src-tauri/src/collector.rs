@@ -0,0 +1,183 @@
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::models::DeviceSignal;
+use crate::config::AppConfig;
+use crate::services::SharedDataService;
+use crate::sink;
+use crate::{kafka_consumer, modbus, mqtt, opcua_client};
+
+/// 数据采集来源，对应配置中的同名段
+const SOURCES: [&str; 4] = ["kafka_consumer", "mqtt", "modbus", "opcua"];
+/// 写入任务每批最多写入的信号数量
+pub const MAX_BATCH_SIZE: usize = 500;
+/// 写入采集信号失败后重试的最长等待时间
+const MAX_PERSIST_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// 等待其他请求写入相同ID信号的检查间隔
+const IN_FLIGHT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// 后台数据采集任务
+///
+/// 重新加载配置时只重启配置有变化的采集来源，其余来源的连接保持不变。
+/// 任务始终运行在启动时的Tokio运行时中。
+pub struct Collectors {
+    runtime: Handle,
+    data_service: SharedDataService,
+    config: AppConfig,
+    tasks: HashMap<&'static str, Vec<JoinHandle<()>>>,
+}
+
+impl Collectors {
+    /// 按配置启动所有启用的采集来源
+    pub fn start(config: &AppConfig, data_service: SharedDataService) -> Self {
+        let mut collectors = Self {
+            runtime: Handle::current(),
+            data_service,
+            config: config.clone(),
+            tasks: HashMap::new(),
+        };
+
+        for source in SOURCES {
+            collectors.spawn(source);
+        }
+        collectors
+    }
+
+    /// 应用新配置，返回被重启的采集来源
+    pub fn reload(&mut self, config: &AppConfig) -> Vec<&'static str> {
+        let changed: Vec<&'static str> = SOURCES
+            .into_iter()
+            .filter(|source| section(&self.config, source) != section(config, source))
+            .collect();
+
+        self.config = config.clone();
+        for source in &changed {
+            log::info!("Restarting {} ingestion with new configuration", source);
+            self.stop(source);
+            self.spawn(source);
+        }
+        changed
+    }
+
+    fn spawn(&mut self, source: &'static str) {
+        let _runtime = self.runtime.enter();
+        let data_service = self.data_service.clone();
+
+        let tasks = match source {
+            "kafka_consumer" => kafka_consumer::spawn(&self.config.kafka_consumer, data_service),
+            "mqtt" => mqtt::spawn(&self.config.mqtt, data_service),
+            "modbus" => modbus::spawn(&self.config.modbus, data_service),
+            "opcua" => opcua_client::spawn(&self.config.opcua, data_service),
+            _ => Vec::new(),
+        };
+        self.tasks.insert(source, tasks);
+    }
+
+    fn stop(&mut self, source: &str) {
+        for task in self.tasks.remove(source).unwrap_or_default() {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for Collectors {
+    fn drop(&mut self) {
+        for source in SOURCES {
+            self.stop(source);
+        }
+    }
+}
+
+/// 影响采集来源的配置段，MQTT在订阅时按`ingest`校验信号
+fn section(config: &AppConfig, source: &str) -> serde_json::Value {
+    match source {
+        "kafka_consumer" => to_value(&config.kafka_consumer),
+        "mqtt" => serde_json::json!([to_value(&config.mqtt), to_value(&config.ingest)]),
+        "modbus" => to_value(&config.modbus),
+        "opcua" => to_value(&config.opcua),
+        _ => serde_json::Value::Null,
+    }
+}
+
+fn to_value<T: Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+/// 合并已到达的信号并批量写入数据服务
+///
+/// 推送式采集来源（MQTT、OPC UA）共用。写入失败时重试，重试期间新信号在通道中排队。
+pub async fn run_writer(source: &'static str, mut receiver: mpsc::Receiver<Vec<DeviceSignal>>, data_service: SharedDataService) {
+    while let Some(mut signals) = receiver.recv().await {
+        while signals.len() < MAX_BATCH_SIZE {
+            match receiver.try_recv() {
+                Ok(more) => signals.extend(more),
+                Err(_) => break,
+            }
+        }
+
+        persist(source, &data_service, &signals).await;
+    }
+}
+
+/// 写入数据服务，失败时按指数退避重试直到成功
+///
+/// 相同ID的信号正在由其他请求写入时，等待其完成后重新检查，对方写入失败时由这里写入。
+pub async fn persist(source: &str, data_service: &SharedDataService, signals: &[DeviceSignal]) {
+    let mut signals = Cow::Borrowed(signals);
+
+    loop {
+        let service = data_service.current();
+        let (fresh, in_flight) = service.reserve_batch(&signals);
+        if !fresh.is_empty() {
+            write_with_retry(source, data_service, &fresh).await;
+            service.settle_batch(&fresh, true);
+        }
+
+        if in_flight.is_empty() {
+            return;
+        }
+        log::debug!("Waiting for {} {} signals that are still being written by another request", in_flight.len(), source);
+        tokio::time::sleep(IN_FLIGHT_RETRY_DELAY).await;
+        signals = Cow::Owned(in_flight);
+    }
+}
+
+/// 写入失败时按指数退避重试，直到所有sink写入成功或拒绝数据
+///
+/// 只重试失败的sink，已写入成功的sink不会收到重复数据；
+/// 被sink拒绝的数据重试也不会成功，记录错误后不再重试该sink。
+async fn write_with_retry(source: &str, data_service: &SharedDataService, signals: &[DeviceSignal]) {
+    let mut pending: Option<BTreeSet<String>> = None;
+    let mut delay = Duration::from_secs(1);
+
+    loop {
+        let failed = data_service.current().write_batch_to(signals, pending.as_ref()).await;
+
+        let mut retry = BTreeSet::new();
+        for (sink, e) in failed {
+            if sink::is_rejected(&e) {
+                log::error!("{} rejected {} {} signals, dropping them: {}", sink, signals.len(), source, e);
+            } else {
+                retry.insert(sink);
+            }
+        }
+
+        if retry.is_empty() {
+            log::debug!("Persisted {} signals from {}", signals.len(), source);
+            return;
+        }
+
+        let sinks: Vec<&str> = retry.iter().map(|sink| sink.as_str()).collect();
+        log::error!(
+            "Failed to persist {} {} signals to {}, retrying in {:?}",
+            signals.len(), source, sinks.join(", "), delay
+        );
+        pending = Some(retry);
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_PERSIST_RETRY_DELAY);
+    }
+}
@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
-use config::{Config, ConfigError, Environment, File};
+use config::{builder::DefaultState, Config, ConfigBuilder, ConfigError, Environment, File};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::fs;
 use std::io::Write;
+use crate::secrets;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AppConfig {
@@ -10,12 +12,32 @@ pub struct AppConfig {
     pub mariadb: MariaDbConfig,
     pub influxdb: InfluxDbConfig,
     pub kafka: KafkaConfig,
+    pub kafka_consumer: KafkaConsumerConfig,
+    pub mqtt: MqttConfig,
+    pub modbus: ModbusConfig,
+    pub opcua: OpcUaConfig,
+    pub sinks: SinksConfig,
+    pub buffer: BufferConfig,
+    pub ingest: IngestConfig,
+    pub auth: AuthConfig,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    /// 启用HTTPS
+    pub tls_enabled: bool,
+    /// PEM格式的证书（链）文件
+    pub tls_cert_path: Option<String>,
+    /// PEM格式的私钥文件
+    pub tls_key_path: Option<String>,
+    /// 签发设备客户端证书的CA，设置后启用双向TLS
+    pub tls_client_ca_path: Option<String>,
+    /// 双向TLS时是否拒绝未提供客户端证书的连接
+    pub tls_client_auth_required: bool,
+    /// 证书文件变化的检查间隔（秒），0表示不自动重新加载
+    pub tls_reload_interval_secs: u64,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -24,7 +46,10 @@ pub struct MariaDbConfig {
     pub port: u16,
     pub database: String,
     pub username: String,
+    /// 支持`env:`、`file:`和`enc:`引用，见`secrets::resolve`
     pub password: String,
+    /// 批量插入时每条INSERT语句包含的行数
+    pub batch_size: usize,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -33,13 +58,342 @@ pub struct InfluxDbConfig {
     pub database: String,
     pub username: Option<String>,
     pub password: Option<String>,
+    /// 写入时间戳精度: ns, us, ms, s
+    pub precision: String,
+    /// 写入的保留策略，未设置时使用数据库默认策略
+    pub retention_policy: Option<String>,
+    /// 每个写入请求包含的最大数据点数
+    pub batch_size: usize,
+    /// 默认写入的measurement
+    pub measurement: String,
+    /// 按信号类型指定measurement，未列出的信号类型写入默认measurement
+    #[serde(default)]
+    pub measurements: HashMap<String, String>,
+    /// 写为标签的metadata键
+    pub tag_keys: Vec<String>,
+    /// 写为字段的metadata键
+    pub field_keys: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct KafkaConfig {
     pub brokers: String,
+    /// 未匹配任何路由规则的信号写入的主题
     pub topic: String,
     pub client_id: String,
+    /// 消息键策略，决定信号写入的分区
+    #[serde(default)]
+    pub key_strategy: KafkaKeyStrategy,
+    /// 主题路由规则，按顺序匹配，使用第一个匹配规则的主题
+    #[serde(default)]
+    pub routes: Vec<KafkaRouteConfig>,
+    /// 确认级别: all, 1, 0
+    pub acks: String,
+    /// 幂等生产者，避免重试产生重复消息，要求`acks = "all"`
+    pub enable_idempotence: bool,
+    /// 压缩算法: none, gzip, snappy, lz4, zstd
+    pub compression: String,
+    /// 发送前等待凑批的时间（毫秒）
+    pub linger_ms: u64,
+    /// 每批最多包含的消息数
+    pub batch_size: usize,
+    /// 本地发送队列最多缓存的消息数
+    pub queue_max_messages: usize,
+    /// 消息投递超时（毫秒），包含重试时间
+    pub message_timeout_ms: u64,
+    /// 本地队列已满时等待入队的最长时间（毫秒）
+    pub send_timeout_ms: u64,
+    /// 连接broker的认证和加密设置
+    #[serde(flatten)]
+    pub security: KafkaSecurityConfig,
+    /// 附加的librdkafka配置，优先于以上设置
+    #[serde(default)]
+    pub extra: HashMap<String, String>,
+}
+
+/// `kafka.extra`中按密码处理的librdkafka配置项，保存时加密，返回前端时隐藏
+pub const KAFKA_SECRET_PROPERTIES: [&str; 5] = [
+    "sasl.password",
+    "ssl.key.password",
+    "ssl.keystore.password",
+    "ssl.truststore.password",
+    "sasl.oauthbearer.client.secret",
+];
+
+/// Kafka连接的安全设置，生产者和消费者共用
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct KafkaSecurityConfig {
+    /// 安全协议: plaintext, ssl, sasl_plaintext, sasl_ssl
+    pub security_protocol: String,
+    /// SASL机制: PLAIN, SCRAM-SHA-256, SCRAM-SHA-512
+    pub sasl_mechanism: Option<String>,
+    pub sasl_username: Option<String>,
+    /// 支持`env:`、`file:`和`enc:`引用
+    pub sasl_password: Option<String>,
+    /// 校验broker证书的CA文件（PEM）
+    pub ssl_ca_location: Option<String>,
+    /// 客户端证书（PEM），broker要求双向TLS时配置
+    pub ssl_certificate_location: Option<String>,
+    /// 客户端私钥（PEM）
+    pub ssl_key_location: Option<String>,
+    /// 客户端私钥的密码，支持`env:`、`file:`和`enc:`引用
+    pub ssl_key_password: Option<String>,
+}
+
+/// Kafka消息键策略
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KafkaKeyStrategy {
+    /// `{device_id}_{signal_type}`，同一设备同一信号类型的消息保持顺序
+    #[default]
+    Composite,
+    /// `device_id`，同一设备的所有消息保持顺序
+    Device,
+    /// 信号ID，便于下游按键去重或压缩
+    Id,
+    /// 不设置键，由生产者分配分区
+    None,
+}
+
+/// Kafka主题路由规则，设置的条件全部满足时匹配
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct KafkaRouteConfig {
+    pub topic: String,
+    /// 信号类型，以`*`结尾时按前缀匹配，例如`alarm.*`
+    pub signal_type: Option<String>,
+    /// 设备ID前缀
+    pub device_prefix: Option<String>,
+    /// 标签或metadata中的键
+    pub metadata_key: Option<String>,
+    /// `metadata_key`对应的值，未设置时只要求键存在
+    pub metadata_value: Option<String>,
+}
+
+/// Kafka信号消费者配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct KafkaConsumerConfig {
+    pub enabled: bool,
+    pub brokers: String,
+    pub group_id: String,
+    pub client_id: String,
+    /// 订阅的主题
+    pub topics: Vec<String>,
+    /// 没有已提交偏移量时的起始位置: earliest, latest
+    pub auto_offset_reset: String,
+    /// 每批最多处理的信号数量
+    pub batch_size: usize,
+    /// 凑批的最长等待时间（毫秒）
+    pub batch_timeout_ms: u64,
+    /// 连接broker的认证和加密设置，与`kafka`相同
+    #[serde(flatten)]
+    pub security: KafkaSecurityConfig,
+}
+
+/// MQTT信号采集配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 订阅的主题模板，`{device_id}`和`{signal_type}`占位符从主题中提取对应字段
+    pub topics: Vec<String>,
+    /// 订阅QoS: 0, 1, 2
+    pub qos: u8,
+    pub keep_alive_secs: u64,
+}
+
+/// Modbus TCP轮询配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ModbusConfig {
+    pub enabled: bool,
+    pub devices: Vec<ModbusDeviceConfig>,
+}
+
+/// 单个Modbus TCP设备
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ModbusDeviceConfig {
+    pub device_id: String,
+    pub host: String,
+    #[serde(default = "default_modbus_port")]
+    pub port: u16,
+    #[serde(default = "default_modbus_unit_id")]
+    pub unit_id: u8,
+    #[serde(default = "default_modbus_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    pub registers: Vec<ModbusRegisterConfig>,
+}
+
+/// 寄存器映射，信号值为`原始值 * scale + offset`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ModbusRegisterConfig {
+    pub address: u16,
+    #[serde(default)]
+    pub kind: ModbusRegisterKind,
+    #[serde(default)]
+    pub data_type: ModbusDataType,
+    #[serde(default)]
+    pub word_order: ModbusWordOrder,
+    #[serde(default = "default_modbus_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+    pub unit: Option<String>,
+    pub signal_type: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ModbusRegisterKind {
+    #[default]
+    Holding,
+    Input,
+    Coil,
+    Discrete,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ModbusDataType {
+    Bool,
+    #[default]
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+}
+
+/// 32位数值的字序，`big`表示高位字在前
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ModbusWordOrder {
+    #[default]
+    Big,
+    Little,
+}
+
+fn default_modbus_port() -> u16 {
+    502
+}
+
+fn default_modbus_unit_id() -> u8 {
+    1
+}
+
+fn default_modbus_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_modbus_scale() -> f64 {
+    1.0
+}
+
+/// OPC UA订阅配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct OpcUaConfig {
+    pub enabled: bool,
+    pub endpoint_url: String,
+    /// 节点未单独指定时使用的设备ID
+    pub device_id: String,
+    /// 用户名认证要求启用消息安全，避免密码以明文传输
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 安全策略: None, Basic128Rsa15, Basic256, Basic256Sha256, Aes128Sha256RsaOaep, Aes256Sha256RsaPss
+    pub security_policy: String,
+    /// 消息安全模式: None, Sign, SignAndEncrypt
+    pub security_mode: String,
+    /// 自动信任未知的服务器证书，仅用于测试；默认需要将服务器证书放入PKI目录的`trusted/certs`
+    pub trust_server_certs: bool,
+    /// 客户端证书和受信任证书所在的PKI目录，未设置时为配置目录下的`pki`
+    pub pki_dir: Option<String>,
+    pub publishing_interval_ms: u64,
+    pub nodes: Vec<OpcUaNodeConfig>,
+}
+
+impl OpcUaConfig {
+    /// 客户端证书和受信任证书所在的PKI目录
+    pub fn pki_dir(&self) -> PathBuf {
+        match &self.pki_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let mut dir = AppConfig::get_config_path();
+                dir.pop();
+                dir.push("pki");
+                dir
+            }
+        }
+    }
+}
+
+/// 监控的OPC UA节点
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct OpcUaNodeConfig {
+    /// 节点ID，例如`ns=2;s=Line3.Temperature`
+    pub node_id: String,
+    /// 信号类型，未设置时使用节点的浏览名
+    pub signal_type: Option<String>,
+    pub device_id: Option<String>,
+    pub unit: Option<String>,
+}
+
+/// 信号写入目标配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SinksConfig {
+    /// 启用的sink，可选值: mariadb, influxdb, kafka
+    pub enabled: Vec<String>,
+}
+
+/// 本地存储转发缓冲配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct BufferConfig {
+    pub enabled: bool,
+    /// 缓冲文件目录，未设置时使用配置目录下的`buffer`
+    pub directory: Option<String>,
+    /// 每个sink最多缓冲的信号数量，超出时丢弃最旧的数据
+    pub max_entries: usize,
+    /// 缓冲数据保留时长（小时）
+    pub retention_hours: u64,
+    /// 重放检查间隔（秒）
+    pub replay_interval_secs: u64,
+    /// 每次重放写入的信号数量
+    pub replay_batch_size: usize,
+}
+
+/// 信号接入校验配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct IngestConfig {
+    /// 设备时间戳允许超前网关时间的最大秒数
+    pub max_clock_skew_secs: u64,
+    /// 设备时间戳允许的最大延迟（秒），0表示不限制
+    pub max_signal_age_secs: u64,
+    /// 单个信号metadata序列化后的最大字节数
+    pub max_metadata_bytes: usize,
+    /// 多字段信号的最大字段数
+    pub max_fields: usize,
+    /// 批量接口单次请求的最大信号数
+    pub max_batch_size: usize,
+    /// HTTP请求体的最大字节数
+    pub max_body_bytes: usize,
+    /// 客户端提供ID的信号去重时间窗口（秒），0表示不去重
+    pub dedup_window_secs: u64,
+    /// 去重缓存最多记录的信号ID数量
+    pub dedup_capacity: usize,
+}
+
+/// HTTP接口认证配置
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AuthConfig {
+    /// 启用后除健康检查外的接口都需要API密钥或请求签名
+    pub enabled: bool,
+    /// 签名请求的时间戳与网关时间允许的最大偏差（秒）
+    pub max_clock_skew_secs: u64,
+    /// 密钥查询结果的缓存时间（秒），0表示每次请求都查询数据库
+    pub cache_ttl_secs: u64,
+    /// 密钥库不可用时，上次查询结果的最长可用时间（秒），0表示不使用上次的结果
+    pub offline_key_max_age_secs: u64,
 }
 
 impl AppConfig {
@@ -57,22 +411,90 @@ impl AppConfig {
         config_dir
     }
     
-    /// 从外部配置文件加载配置
-    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, ConfigError> {
-        let mut config = Config::builder()
+    /// 带默认值的配置构建器
+    fn default_builder() -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+        Config::builder()
             // 默认配置
             .set_default("server.host", "127.0.0.1")?
             .set_default("server.port", 8080)?
+            .set_default("server.tls_enabled", false)?
+            .set_default("server.tls_client_auth_required", true)?
+            .set_default("server.tls_reload_interval_secs", 30)?
             .set_default("mariadb.host", "localhost")?
             .set_default("mariadb.port", 3306)?
             .set_default("mariadb.database", "ps_v2")?
             .set_default("mariadb.username", "pike")?
             .set_default("mariadb.password", "pike")?
+            .set_default("mariadb.batch_size", 500)?
             .set_default("influxdb.url", "http://192.168.5.199:8086")?
             .set_default("influxdb.database", "device_signals")?
+            .set_default("influxdb.precision", "ms")?
+            .set_default("influxdb.batch_size", 5000)?
+            .set_default("influxdb.measurement", "device_signals")?
+            .set_default("influxdb.tag_keys", Vec::<String>::new())?
+            .set_default("influxdb.field_keys", Vec::<String>::new())?
             .set_default("kafka.brokers", "192.168.5.199:9092")?
             .set_default("kafka.topic", "device-signals")?
-            .set_default("kafka.client_id", "tauri-device-gateway")?;
+            .set_default("kafka.client_id", "tauri-device-gateway")?
+            .set_default("kafka.key_strategy", "composite")?
+            .set_default("kafka.acks", "all")?
+            .set_default("kafka.enable_idempotence", true)?
+            .set_default("kafka.compression", "none")?
+            .set_default("kafka.linger_ms", 5)?
+            .set_default("kafka.batch_size", 1000)?
+            .set_default("kafka.queue_max_messages", 100_000)?
+            .set_default("kafka.message_timeout_ms", 30_000)?
+            .set_default("kafka.send_timeout_ms", 5000)?
+            .set_default("kafka.security_protocol", "plaintext")?
+            .set_default("kafka_consumer.enabled", false)?
+            .set_default("kafka_consumer.brokers", "192.168.5.199:9092")?
+            .set_default("kafka_consumer.group_id", "tauri-device-gateway")?
+            .set_default("kafka_consumer.client_id", "tauri-device-gateway-consumer")?
+            .set_default("kafka_consumer.topics", Vec::<String>::new())?
+            .set_default("kafka_consumer.auto_offset_reset", "earliest")?
+            .set_default("kafka_consumer.batch_size", 500)?
+            .set_default("kafka_consumer.batch_timeout_ms", 1000)?
+            .set_default("kafka_consumer.security_protocol", "plaintext")?
+            .set_default("mqtt.enabled", false)?
+            .set_default("mqtt.host", "localhost")?
+            .set_default("mqtt.port", 1883)?
+            .set_default("mqtt.client_id", "tauri-device-gateway")?
+            .set_default("mqtt.topics", vec!["devices/{device_id}/signals/{signal_type}"])?
+            .set_default("mqtt.qos", 1)?
+            .set_default("mqtt.keep_alive_secs", 30)?
+            .set_default("modbus.enabled", false)?
+            .set_default("modbus.devices", Vec::<String>::new())?
+            .set_default("opcua.enabled", false)?
+            .set_default("opcua.endpoint_url", "opc.tcp://localhost:4840")?
+            .set_default("opcua.device_id", "opcua-server")?
+            .set_default("opcua.security_policy", "None")?
+            .set_default("opcua.security_mode", "None")?
+            .set_default("opcua.trust_server_certs", false)?
+            .set_default("opcua.publishing_interval_ms", 1000)?
+            .set_default("opcua.nodes", Vec::<String>::new())?
+            .set_default("sinks.enabled", vec!["mariadb", "influxdb", "kafka"])?
+            .set_default("buffer.enabled", true)?
+            .set_default("buffer.max_entries", 100_000)?
+            .set_default("buffer.retention_hours", 72)?
+            .set_default("buffer.replay_interval_secs", 10)?
+            .set_default("buffer.replay_batch_size", 500)?
+            .set_default("ingest.max_clock_skew_secs", 300)?
+            .set_default("ingest.max_signal_age_secs", 0)?
+            .set_default("ingest.max_metadata_bytes", 16_384)?
+            .set_default("ingest.max_fields", 100)?
+            .set_default("ingest.max_batch_size", 10_000)?
+            .set_default("ingest.max_body_bytes", 10 * 1024 * 1024)?
+            .set_default("ingest.dedup_window_secs", 600)?
+            .set_default("ingest.dedup_capacity", 100_000)?
+            .set_default("auth.enabled", false)?
+            .set_default("auth.max_clock_skew_secs", 300)?
+            .set_default("auth.cache_ttl_secs", 60)?
+            .set_default("auth.offline_key_max_age_secs", 900)
+    }
+
+    /// 从外部配置文件加载配置
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, ConfigError> {
+        let mut config = Self::default_builder()?;
 
         // 从指定文件加载
         if path.as_ref().exists() {
@@ -88,9 +510,68 @@ impl AppConfig {
         config.build()?.try_deserialize()
     }
     
-    /// 保存配置到外部文件
+    /// 敏感配置项及其名称，新增密码类配置时需要加入此列表
+    fn secrets_mut(&mut self) -> Vec<(String, &mut String)> {
+        let mut secrets: Vec<(String, &mut String)> = vec![("mariadb.password".to_string(), &mut self.mariadb.password)];
+
+        let optional = [
+            ("influxdb.password", self.influxdb.password.as_mut()),
+            ("kafka.sasl_password", self.kafka.security.sasl_password.as_mut()),
+            ("kafka.ssl_key_password", self.kafka.security.ssl_key_password.as_mut()),
+            ("kafka_consumer.sasl_password", self.kafka_consumer.security.sasl_password.as_mut()),
+            ("kafka_consumer.ssl_key_password", self.kafka_consumer.security.ssl_key_password.as_mut()),
+            ("mqtt.password", self.mqtt.password.as_mut()),
+            ("opcua.password", self.opcua.password.as_mut()),
+        ];
+        for (name, secret) in optional {
+            if let Some(secret) = secret {
+                secrets.push((name.to_string(), secret));
+            }
+        }
+
+        for (key, value) in self.kafka.extra.iter_mut() {
+            if KAFKA_SECRET_PROPERTIES.contains(&key.as_str()) {
+                secrets.push((format!("kafka.extra.{}", key), value));
+            }
+        }
+
+        secrets
+    }
+
+    /// 返回给前端的配置，明文和加密的密码替换为占位符，环境变量和文件引用保留
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        for (_, secret) in config.secrets_mut() {
+            if !secret.is_empty() && !secrets::is_reference(secret) {
+                *secret = secrets::REDACTED.to_string();
+            }
+        }
+        config
+    }
+
+    /// 前端提交的配置中仍为占位符的密码恢复为原值
+    pub fn restore_redacted(&mut self, previous: &AppConfig) {
+        let mut previous = previous.clone();
+        let originals: HashMap<String, &mut String> = previous.secrets_mut().into_iter().collect();
+        for (name, secret) in self.secrets_mut() {
+            if secret == secrets::REDACTED {
+                if let Some(original) = originals.get(&name) {
+                    *secret = original.to_string();
+                }
+            }
+        }
+    }
+
+    /// 保存配置到外部文件，明文密码加密后写入
     pub fn save_to_file<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
-        let toml_string = toml::to_string_pretty(self)?;
+        let mut config = self.clone();
+        for (_, secret) in config.secrets_mut() {
+            if !secret.is_empty() && !secrets::is_reference(secret) && !secrets::is_encrypted(secret) {
+                *secret = secrets::encrypt(secret)?;
+            }
+        }
+
+        let toml_string = toml::to_string_pretty(&config)?;
         
         // 确保父目录存在
         if let Some(parent) = path.as_ref().parent() {
@@ -120,20 +601,7 @@ impl AppConfig {
         }
         
         // 否则使用默认配置并保存到外部文件
-        let mut config = Config::builder()
-            // 默认配置
-            .set_default("server.host", "127.0.0.1")?
-            .set_default("server.port", 8080)?
-            .set_default("mariadb.host", "localhost")?
-            .set_default("mariadb.port", 3306)?
-            .set_default("mariadb.database", "ps_v2")?
-            .set_default("mariadb.username", "pike")?
-            .set_default("mariadb.password", "pike")?
-            .set_default("influxdb.url", "http://192.168.5.199:8086")?
-            .set_default("influxdb.database", "device_signals")?
-            .set_default("kafka.brokers", "192.168.5.199:9092")?
-            .set_default("kafka.topic", "device-signals")?
-            .set_default("kafka.client_id", "tauri-device-gateway")?;
+        let mut config = Self::default_builder()?;
 
         // 尝试从当前目录的 config.toml 加载（兼容性）
         if let Ok(_) = std::fs::metadata("config.toml") {
@@ -153,6 +621,40 @@ impl AppConfig {
         Ok(app_config)
     }
 
+    /// 本地缓冲目录
+    pub fn buffer_dir(&self) -> PathBuf {
+        match &self.buffer.directory {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let mut dir = Self::get_config_path();
+                dir.pop();
+                dir.push("buffer");
+                dir
+            }
+        }
+    }
+
+    /// 与`previous`相比有变化、需要重启应用才能生效的配置项
+    ///
+    /// 监听地址、TLS设置和请求体大小限制在HTTP服务器启动时确定，重新加载服务不会应用。
+    pub fn restart_required(&self, previous: &AppConfig) -> Vec<String> {
+        let server = serde_json::to_value(&self.server).unwrap_or_default();
+        let previous_server = serde_json::to_value(&previous.server).unwrap_or_default();
+
+        let mut changed: Vec<String> = match (server.as_object(), previous_server.as_object()) {
+            (Some(server), Some(previous_server)) => server
+                .iter()
+                .filter(|(key, value)| previous_server.get(key.as_str()) != Some(value))
+                .map(|(key, _)| format!("server.{}", key))
+                .collect(),
+            _ => Vec::new(),
+        };
+        if self.ingest.max_body_bytes != previous.ingest.max_body_bytes {
+            changed.push("ingest.max_body_bytes".to_string());
+        }
+        changed
+    }
+
     pub fn mariadb_url(&self) -> String {
         format!(
             "mysql://{}:{}@{}:{}/{}",
@@ -0,0 +1,151 @@
+use serde::Serialize;
+use anyhow::Result;
+use std::future::Future;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+
+/// 首次重连等待时间
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// 重连等待时间上限
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// 外部服务连接状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ConnectionStatus {
+    Connected,
+    Disconnected,
+}
+
+type ClientSlot<T> = RwLock<Option<Arc<T>>>;
+
+/// 可选的外部服务客户端
+///
+/// 初始化失败时不会中断启动，而是标记为`Disconnected`并在后台按指数退避不断重连，
+/// 连接成功后自动切换为`Connected`。
+pub struct ManagedClient<T> {
+    name: &'static str,
+    slot: Arc<ClientSlot<T>>,
+    /// 配置错误，重连无法恢复，不启动后台重连
+    config_error: Option<Arc<String>>,
+}
+
+impl<T> Clone for ManagedClient<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            slot: self.slot.clone(),
+            config_error: self.config_error.clone(),
+        }
+    }
+}
+
+impl<T: Send + Sync + 'static> ManagedClient<T> {
+    /// 尝试连接，失败时启动后台重连任务
+    pub async fn connect<F, Fut>(name: &'static str, connector: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        let slot = Arc::new(RwLock::new(None));
+
+        match connector().await {
+            Ok(client) => {
+                log::info!("{} client initialized", name);
+                Self::store(&slot, client);
+            },
+            Err(e) => {
+                log::warn!("Failed to initialize {}: {}", name, e);
+                log::warn!("{} marked as disconnected, retrying in background", name);
+                tokio::spawn(Self::retry(name, Arc::downgrade(&slot), connector));
+            }
+        }
+
+        Self { name, slot, config_error: None }
+    }
+
+    /// 配置无效的客户端，始终为`Disconnected`，修正配置并重新加载服务后才会连接
+    pub fn invalid(name: &'static str, error: anyhow::Error) -> Self {
+        log::error!("❌ {} configuration is invalid, not connecting: {:#}", name, error);
+
+        Self {
+            name,
+            slot: Arc::new(RwLock::new(None)),
+            config_error: Some(Arc::new(format!("{:#}", error))),
+        }
+    }
+
+    /// 服务名称
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// 当前连接状态
+    pub fn status(&self) -> ConnectionStatus {
+        let guard = match self.slot.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if guard.is_some() {
+            ConnectionStatus::Connected
+        } else {
+            ConnectionStatus::Disconnected
+        }
+    }
+
+    /// 获取已连接的客户端，未连接时返回错误
+    pub fn get(&self) -> Result<Arc<T>> {
+        let guard = match self.slot.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        guard.clone().ok_or_else(|| match &self.config_error {
+            Some(error) => anyhow::anyhow!("{} configuration is invalid: {}", self.name, error),
+            None => anyhow::anyhow!("{} is disconnected", self.name),
+        })
+    }
+
+    fn store(slot: &ClientSlot<T>, client: T) {
+        let mut guard = match slot.write() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *guard = Some(Arc::new(client));
+    }
+
+    /// 指数退避重连，所属服务被释放（例如热重载后）时停止
+    async fn retry<F, Fut>(name: &'static str, slot: Weak<ClientSlot<T>>, connector: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        let mut delay = INITIAL_RETRY_DELAY;
+
+        loop {
+            tokio::time::sleep(delay).await;
+
+            if slot.strong_count() == 0 {
+                log::debug!("{} retry stopped: service was replaced", name);
+                return;
+            }
+
+            match connector().await {
+                Ok(client) => {
+                    match slot.upgrade() {
+                        Some(slot) => {
+                            Self::store(&slot, client);
+                            log::info!("✅ {} reconnected", name);
+                        },
+                        None => log::debug!("{} reconnected after service was replaced", name),
+                    }
+                    return;
+                },
+                Err(e) => {
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                    log::warn!("{} reconnect failed: {} (next attempt in {:?})", name, e, delay);
+                }
+            }
+        }
+    }
+}
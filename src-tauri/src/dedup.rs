@@ -0,0 +1,256 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, Weak};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use crate::config::IngestConfig;
+
+/// 进程内共享的去重缓存，热重载前后的服务使用同一份记录
+static SHARED_CACHE: OnceLock<Mutex<Weak<DedupCache>>> = OnceLock::new();
+
+/// 信号ID的去重检查结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reservation {
+    /// 未接收过，调用方写入后需要调用`commit`或`release`
+    Reserved,
+    /// 时间窗口内已成功写入过
+    Duplicate,
+    /// 相同ID的信号正在写入，结果未知
+    InFlight,
+}
+
+/// 相同ID的信号仍在写入中，稍后重试
+#[derive(Debug, thiserror::Error)]
+#[error("Signal {0} is still being processed, retry later")]
+pub struct InFlight(pub Uuid);
+
+struct DedupState {
+    capacity: usize,
+    window: Duration,
+    /// 已成功写入的信号ID及记录时间
+    seen: HashMap<Uuid, Instant>,
+    /// 正在写入的信号ID及开始时间，写入完成前不计为重复
+    ///
+    /// 写入被取消（例如客户端断开连接）时记录不会被撤销，超过时间窗口后自动清除。
+    in_flight: HashMap<Uuid, Instant>,
+    /// 按记录时间排序，用于淘汰过期或超出容量的条目
+    order: VecDeque<(Uuid, Instant)>,
+}
+
+impl DedupState {
+    fn evict(&mut self, now: Instant) {
+        while let Some(&(id, recorded_at)) = self.order.front() {
+            let expired = now.duration_since(recorded_at) >= self.window;
+            if !expired && self.order.len() <= self.capacity {
+                break;
+            }
+
+            self.order.pop_front();
+            // 同一ID在队列中可能有更新的条目，只删除与当前记录对应的那一条
+            if self.seen.get(&id) == Some(&recorded_at) {
+                self.seen.remove(&id);
+            }
+        }
+
+        let window = self.window;
+        self.in_flight.retain(|_, started_at| now.duration_since(*started_at) < window);
+    }
+}
+
+/// 客户端提供ID的信号去重缓存
+///
+/// 在时间窗口内记录已成功写入的信号ID，容量有限，超出时淘汰最早的记录。
+/// 正在写入的ID单独记录，写入成功后才会把重试的信号当作重复。
+pub struct DedupCache {
+    state: Mutex<DedupState>,
+}
+
+impl DedupCache {
+    /// 获取（或创建）进程内共享的去重缓存
+    pub fn shared(config: &IngestConfig) -> Arc<Self> {
+        let registry = SHARED_CACHE.get_or_init(|| Mutex::new(Weak::new()));
+        let mut registry = match registry.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if let Some(cache) = registry.upgrade() {
+            // 复用已有缓存，仅更新限制
+            let mut state = cache.lock();
+            state.capacity = config.dedup_capacity;
+            state.window = Duration::from_secs(config.dedup_window_secs);
+            drop(state);
+            return cache;
+        }
+
+        let cache = Arc::new(Self {
+            state: Mutex::new(DedupState {
+                capacity: config.dedup_capacity,
+                window: Duration::from_secs(config.dedup_window_secs),
+                seen: HashMap::new(),
+                in_flight: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        });
+        *registry = Arc::downgrade(&cache);
+
+        cache
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DedupState> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// 检查信号ID，未接收过时标记为正在写入
+    pub fn reserve(&self, id: Uuid) -> Reservation {
+        let mut state = self.lock();
+        if state.capacity == 0 || state.window.is_zero() {
+            return Reservation::Reserved;
+        }
+
+        let now = Instant::now();
+        state.evict(now);
+
+        if state.seen.contains_key(&id) {
+            Reservation::Duplicate
+        } else if state.in_flight.insert(id, now).is_some() {
+            Reservation::InFlight
+        } else {
+            Reservation::Reserved
+        }
+    }
+
+    /// 写入成功，时间窗口内重试的信号视为重复
+    pub fn commit(&self, id: Uuid) {
+        let mut state = self.lock();
+        if state.in_flight.remove(&id).is_none() {
+            return;
+        }
+
+        let now = Instant::now();
+        state.seen.insert(id, now);
+        state.order.push_back((id, now));
+        state.evict(now);
+    }
+
+    /// 写入失败时撤销记录，允许客户端重试
+    pub fn release(&self, id: Uuid) {
+        self.lock().in_flight.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(capacity: usize, window: Duration) -> DedupCache {
+        DedupCache {
+            state: Mutex::new(DedupState {
+                capacity,
+                window,
+                seen: HashMap::new(),
+                in_flight: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    fn written(cache: &DedupCache, id: Uuid) {
+        assert_eq!(cache.reserve(id), Reservation::Reserved);
+        cache.commit(id);
+    }
+
+    #[test]
+    fn in_flight_ids_are_not_duplicates() {
+        let cache = cache(10, Duration::from_secs(60));
+        let id = Uuid::new_v4();
+
+        assert_eq!(cache.reserve(id), Reservation::Reserved);
+        assert_eq!(cache.reserve(id), Reservation::InFlight);
+
+        cache.commit(id);
+        assert_eq!(cache.reserve(id), Reservation::Duplicate);
+    }
+
+    #[test]
+    fn released_ids_can_be_reserved_again() {
+        let cache = cache(10, Duration::from_secs(60));
+        let id = Uuid::new_v4();
+
+        assert_eq!(cache.reserve(id), Reservation::Reserved);
+        cache.release(id);
+        assert_eq!(cache.reserve(id), Reservation::Reserved);
+
+        cache.commit(id);
+        assert_eq!(cache.reserve(id), Reservation::Duplicate);
+        // 已提交的ID不受迟到的release影响
+        cache.release(id);
+        assert_eq!(cache.reserve(id), Reservation::Duplicate);
+    }
+
+    #[test]
+    fn ids_expire_after_window() {
+        let window = Duration::from_millis(50);
+        let cache = cache(10, window);
+        let id = Uuid::new_v4();
+
+        written(&cache, id);
+        assert_eq!(cache.reserve(id), Reservation::Duplicate);
+
+        std::thread::sleep(window * 2);
+        assert_eq!(cache.reserve(id), Reservation::Reserved);
+    }
+
+    #[test]
+    fn abandoned_in_flight_ids_expire_after_window() {
+        let window = Duration::from_millis(50);
+        let cache = cache(10, window);
+        let id = Uuid::new_v4();
+
+        assert_eq!(cache.reserve(id), Reservation::Reserved);
+        assert_eq!(cache.reserve(id), Reservation::InFlight);
+
+        std::thread::sleep(window * 2);
+        assert_eq!(cache.reserve(id), Reservation::Reserved);
+    }
+
+    #[test]
+    fn oldest_ids_are_evicted_at_capacity() {
+        let cache = cache(2, Duration::from_secs(60));
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+
+        for id in &ids {
+            written(&cache, *id);
+        }
+
+        assert_eq!(cache.reserve(ids[0]), Reservation::Reserved);
+        assert_eq!(cache.reserve(ids[1]), Reservation::Duplicate);
+        assert_eq!(cache.reserve(ids[2]), Reservation::Duplicate);
+    }
+
+    #[test]
+    fn recommitted_id_survives_eviction_of_its_older_entry() {
+        let window = Duration::from_millis(50);
+        let cache = cache(10, window);
+        let id = Uuid::new_v4();
+
+        written(&cache, id);
+        std::thread::sleep(window * 2);
+
+        // 过期后重新写入，队列中旧条目被淘汰时不能删除新的记录
+        written(&cache, id);
+        cache.lock().order.push_front((id, Instant::now() - window * 3));
+        assert_eq!(cache.reserve(id), Reservation::Duplicate);
+    }
+
+    #[test]
+    fn disabled_cache_never_reports_duplicates() {
+        let cache = cache(0, Duration::from_secs(60));
+        let id = Uuid::new_v4();
+
+        written(&cache, id);
+        assert_eq!(cache.reserve(id), Reservation::Reserved);
+    }
+}
@@ -0,0 +1,88 @@
+use axum::{
+    extract::rejection::JsonRejection,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use crate::models::ApiResponse;
+use crate::validation::{FieldError, ValidationErrors};
+
+/// HTTP接口错误
+///
+/// 统一渲染为`ApiResponse::error`，校验失败时附带字段级错误详情。
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    /// 请求参数或格式错误
+    #[error("{0}")]
+    BadRequest(String),
+    /// 请求体解析失败，状态码沿用axum的拒绝原因（400/413/415/422）
+    #[error("{message}")]
+    Rejected { status: StatusCode, message: String },
+    /// 缺少或无效的认证信息
+    #[error("{0}")]
+    Unauthorized(String),
+    /// 认证通过但没有对应权限
+    #[error("{0}")]
+    Forbidden(String),
+    /// 请求内容超出限制
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    /// 相同ID的信号正在处理，稍后重试
+    #[error("{0}")]
+    Conflict(String),
+    /// 请求格式正确但内容未通过校验
+    #[error("{message}")]
+    Validation { message: String, errors: Vec<FieldError> },
+    /// 存储或下游服务不可用
+    #[error("{0}")]
+    ServiceUnavailable(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Rejected { status, .. } => *status,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Validation { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(errors: ValidationErrors) -> Self {
+        ApiError::Validation {
+            message: "Signal validation failed".to_string(),
+            errors: errors.0,
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::Rejected {
+            status: rejection.status(),
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let message = self.to_string();
+
+        let body = match self {
+            ApiError::Validation { errors, .. } => ApiResponse::<()>::error(message).with_errors(errors),
+            _ => ApiResponse::<()>::error(message),
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
@@ -1,25 +1,404 @@
-use influxdb::{Client, InfluxDbWriteable, Timestamp};
+use influxdb::Client;
+use reqwest::StatusCode;
 use anyhow::Result;
-use crate::models::{DeviceSignal, TimeSeriesPoint};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use crate::models::{DeviceSignal, SeriesPoint, SignalQuery, SignalSeries, SignalValue, TimeSeriesPoint};
 use crate::config::InfluxDbConfig;
+use crate::secrets;
+use crate::sink::{Rejected, SignalSink};
+
+/// 网关写入的标签，metadata映射不能覆盖
+const RESERVED_TAGS: [&str; 3] = ["device_id", "signal_type", "unit"];
+
+/// 设备信号到InfluxDB数据点的映射规则
+#[derive(Debug, Clone)]
+pub struct PointMapping {
+    measurement: String,
+    measurements: HashMap<String, String>,
+    tag_keys: Vec<String>,
+    field_keys: Vec<String>,
+}
+
+impl PointMapping {
+    pub fn new(config: &InfluxDbConfig) -> Self {
+        Self {
+            measurement: config.measurement.clone(),
+            measurements: config.measurements.clone(),
+            tag_keys: config.tag_keys.clone(),
+            field_keys: config.field_keys.clone(),
+        }
+    }
+
+    /// 信号类型对应的measurement
+    pub fn measurement(&self, signal_type: &str) -> &str {
+        self.measurements
+            .get(signal_type)
+            .map(String::as_str)
+            .unwrap_or(&self.measurement)
+    }
+
+    /// 查询涉及的measurement，未指定信号类型时包含全部已配置的measurement
+    pub fn measurements_for(&self, signal_types: &[String]) -> Vec<&str> {
+        let mut measurements: Vec<&str> = if signal_types.is_empty() {
+            std::iter::once(self.measurement.as_str())
+                .chain(self.measurements.values().map(String::as_str))
+                .collect()
+        } else {
+            signal_types.iter().map(|t| self.measurement(t)).collect()
+        };
+
+        measurements.sort();
+        measurements.dedup();
+        measurements
+    }
+
+    /// 将信号转换为数据点
+    pub fn to_point(&self, signal: &DeviceSignal) -> TimeSeriesPoint {
+        let mut tags = HashMap::new();
+        tags.insert("device_id".to_string(), signal.device_id.clone());
+        tags.insert("signal_type".to_string(), signal.signal_type.clone());
+
+        if let Some(unit) = &signal.unit {
+            tags.insert("unit".to_string(), unit.clone());
+        }
+
+        for (key, value) in &signal.tags {
+            tags.insert(key.clone(), value.clone());
+        }
+
+        let mut fields = HashMap::new();
+        if let Some(value) = &signal.value {
+            // 非数值信号写入按类型区分的字段，避免同一字段出现多种类型
+            let field = match value {
+                SignalValue::Float(_) | SignalValue::Integer(_) => "value",
+                SignalValue::Boolean(_) => "value_bool",
+                SignalValue::String(_) => "value_str",
+                SignalValue::Json(_) => "value_json",
+            };
+            fields.insert(field.to_string(), field_type(value));
+        }
+        for (field, value) in &signal.fields {
+            fields.insert(field.clone(), field_type(value));
+        }
+
+        // metadata中配置的键映射为标签或字段，不覆盖信号本身的标签和字段
+        if let Some(serde_json::Value::Object(metadata)) = &signal.metadata {
+            for key in &self.tag_keys {
+                if RESERVED_TAGS.contains(&key.as_str()) {
+                    continue;
+                }
+                let value = match metadata.get(key) {
+                    // metadata的值不做字符校验，含控制字符的值不能作为标签
+                    Some(serde_json::Value::String(value)) if value.chars().any(char::is_control) => continue,
+                    Some(serde_json::Value::String(value)) => value.clone(),
+                    Some(serde_json::Value::Null) | None => continue,
+                    Some(other) => other.to_string(),
+                };
+                tags.entry(key.clone()).or_insert(value);
+            }
+
+            for key in &self.field_keys {
+                let value = match metadata.get(key).cloned().map(SignalValue::try_from) {
+                    Some(Ok(value)) => value,
+                    _ => continue,
+                };
+                fields.entry(key.clone()).or_insert_with(|| field_type(&value));
+            }
+        }
+
+        TimeSeriesPoint {
+            measurement: self.measurement(&signal.signal_type).to_string(),
+            tags,
+            fields,
+            timestamp: signal.timestamp,
+        }
+    }
+}
+
+/// `/query`接口响应
+#[derive(Debug, Deserialize)]
+struct QueryResponse {
+    #[serde(default)]
+    results: Vec<QueryResult>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryResult {
+    #[serde(default)]
+    series: Vec<Series>,
+    error: Option<String>,
+}
+
+/// InfluxDB查询结果中的一个序列
+#[derive(Debug, Deserialize)]
+pub struct Series {
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    pub columns: Vec<String>,
+    #[serde(default)]
+    pub values: Vec<Vec<serde_json::Value>>,
+}
+
+impl Series {
+    /// 按列名取值，GROUP BY的标签优先从`tags`中读取
+    pub fn get<'a>(&'a self, row: &'a [serde_json::Value], column: &str) -> Option<&'a serde_json::Value> {
+        self.columns
+            .iter()
+            .position(|c| c == column)
+            .and_then(|index| row.get(index))
+            .filter(|value| !value.is_null())
+    }
+
+    pub fn get_str(&self, row: &[serde_json::Value], column: &str) -> Option<String> {
+        match self.tags.get(column) {
+            Some(value) => Some(value.clone()),
+            None => self.get(row, column).and_then(|v| v.as_str()).map(str::to_string),
+        }
+    }
+
+    pub fn get_time(&self, row: &[serde_json::Value]) -> Result<DateTime<Utc>> {
+        let time = self.get(row, "time")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("InfluxDB row without time column"))?;
+        Ok(DateTime::parse_from_rfc3339(time)?.with_timezone(&Utc))
+    }
+
+    /// 将`GROUP BY *`查询结果转换为设备信号
+    ///
+    /// 标签都在`tags`中，列只包含字段：`value*`字段还原为单值信号的值，其余字段还原为
+    /// 多字段信号的`fields`，metadata映射的标签和字段还原到`metadata`。
+    fn to_signals(&self, mapping: &PointMapping) -> Result<Vec<DeviceSignal>> {
+        // 数据点没有的标签在`GROUP BY *`结果中为空字符串
+        let mut tags = BTreeMap::new();
+        let mut metadata_tags = serde_json::Map::new();
+        for (key, value) in &self.tags {
+            if RESERVED_TAGS.contains(&key.as_str()) || value.is_empty() {
+                continue;
+            }
+            if mapping.tag_keys.contains(key) {
+                metadata_tags.insert(key.clone(), value.clone().into());
+            } else {
+                tags.insert(key.clone(), value.clone());
+            }
+        }
+
+        let mut signals = Vec::new();
+        for row in &self.values {
+            let mut value = None;
+            let mut fields = BTreeMap::new();
+            let mut metadata = metadata_tags.clone();
+
+            for (column, cell) in self.columns.iter().zip(row) {
+                if column == "time" || cell.is_null() {
+                    continue;
+                }
+                match column.as_str() {
+                    "value_json" => {
+                        let text = cell.as_str().ok_or_else(|| anyhow::anyhow!("InfluxDB value_json is not a string"))?;
+                        value = Some(SignalValue::Json(serde_json::from_str(text)?));
+                    },
+                    "value" | "value_bool" | "value_str" => value = stored_value(cell),
+                    key if mapping.field_keys.iter().any(|field| field == key) => {
+                        metadata.insert(key.to_string(), cell.clone());
+                    },
+                    key => {
+                        if let Some(field) = stored_value(cell) {
+                            fields.insert(key.to_string(), field);
+                        }
+                    },
+                }
+            }
+
+            if value.is_none() && fields.is_empty() {
+                continue;
+            }
+
+            signals.push(DeviceSignal {
+                id: None,
+                idempotency_key: None,
+                device_id: self.get_str(row, "device_id").unwrap_or_default(),
+                signal_type: self.get_str(row, "signal_type").unwrap_or_default(),
+                value,
+                fields,
+                tags: tags.clone(),
+                unit: self.get_str(row, "unit").filter(|unit| !unit.is_empty()),
+                timestamp: self.get_time(row)?,
+                received_at: None,
+                metadata: (!metadata.is_empty()).then_some(serde_json::Value::Object(metadata)),
+            });
+        }
+
+        Ok(signals)
+    }
+
+    /// 将按`device_id`和`signal_type`分组的趋势查询结果转换为数据点
+    ///
+    /// 多字段信号的每个数值字段作为`{signal_type}.{field}`单独成为一个序列，与MariaDB中
+    /// 拆分后的信号类型一致；聚合查询的列名为`{聚合函数}_{字段}`。布尔值按1/0计算。
+    fn to_series_points(&self, mapping: &PointMapping, query: &SignalQuery) -> Result<Vec<(String, String, SeriesPoint)>> {
+        let device_id = self.tags.get("device_id").cloned().unwrap_or_default();
+        let signal_type = self.tags.get("signal_type").cloned().unwrap_or_default();
+        let prefix = query.aggregation.map(|(aggregation, _)| format!("{}_", aggregation.as_str()));
+
+        let mut points = Vec::new();
+        for row in &self.values {
+            for (column, cell) in self.columns.iter().zip(row) {
+                let field = match &prefix {
+                    Some(prefix) => match column.strip_prefix(prefix.as_str()) {
+                        Some(field) => field,
+                        None => continue,
+                    },
+                    None => column.as_str(),
+                };
+                if field == "time" || mapping.field_keys.iter().any(|key| key == field) {
+                    continue;
+                }
+
+                let value = match cell {
+                    serde_json::Value::Number(number) => number.as_f64(),
+                    serde_json::Value::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+                    _ => None,
+                };
+                let value = match value {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                let name = field_signal_type(&signal_type, field);
+                if !query.signal_types.is_empty() && !query.signal_types.contains(&name) {
+                    continue;
+                }
+
+                points.push((device_id.clone(), name, SeriesPoint {
+                    timestamp: self.get_time(row)?,
+                    value,
+                }));
+            }
+        }
+
+        Ok(points)
+    }
+}
+
+/// 字段值还原为信号值，数值字段写入时统一为浮点数
+fn stored_value(cell: &serde_json::Value) -> Option<SignalValue> {
+    match cell {
+        serde_json::Value::Number(number) => number.as_f64().map(SignalValue::Float),
+        serde_json::Value::Bool(value) => Some(SignalValue::Boolean(*value)),
+        serde_json::Value::String(value) => Some(SignalValue::String(value.clone())),
+        _ => None,
+    }
+}
+
+/// 字段在趋势查询中的信号类型，`value*`字段属于信号本身，其他字段为多字段信号拆分后的名称
+fn field_signal_type(signal_type: &str, field: &str) -> String {
+    match field {
+        "value" | "value_bool" | "value_str" | "value_json" => signal_type.to_string(),
+        field => format!("{}.{}", signal_type, field),
+    }
+}
+
+/// 查询的信号类型在InfluxDB中对应的`signal_type`标签值
+///
+/// 多字段信号在InfluxDB中是同一数据点的多个字段，按拆分后的`{signal_type}.{field}`查询时
+/// 同时匹配`signal_type`部分。
+fn stored_signal_types(signal_types: &[String]) -> Vec<String> {
+    let mut stored: Vec<String> = signal_types
+        .iter()
+        .flat_map(|signal_type| {
+            let base = signal_type.rsplit_once('.').map(|(base, _)| base.to_string());
+            std::iter::once(signal_type.clone()).chain(base)
+        })
+        .collect();
+    stored.sort();
+    stored.dedup();
+    stored
+}
 
 #[derive(Clone)]
 pub struct InfluxDbClient {
     client: Client,
+    http: reqwest::Client,
+    url: String,
     database: String,
+    username: Option<String>,
+    password: Option<String>,
+    precision: Precision,
+    retention_policy: Option<String>,
+    batch_size: usize,
+    mapping: PointMapping,
+}
+
+/// 写入时间戳精度，对应InfluxDB 1.x `/write`接口的`precision`参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    Nanoseconds,
+    Microseconds,
+    Milliseconds,
+    Seconds,
+}
+
+impl Precision {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "ns" | "n" => Ok(Precision::Nanoseconds),
+            "us" | "u" => Ok(Precision::Microseconds),
+            "ms" => Ok(Precision::Milliseconds),
+            "s" => Ok(Precision::Seconds),
+            other => Err(anyhow::anyhow!("Unsupported InfluxDB precision: {}", other)),
+        }
+    }
+
+    fn as_param(&self) -> &'static str {
+        match self {
+            Precision::Nanoseconds => "n",
+            Precision::Microseconds => "u",
+            Precision::Milliseconds => "ms",
+            Precision::Seconds => "s",
+        }
+    }
+
+    fn timestamp(&self, time: &DateTime<Utc>) -> i64 {
+        match self {
+            Precision::Nanoseconds => time.timestamp_nanos_opt().unwrap_or(i64::MAX),
+            Precision::Microseconds => time.timestamp_micros(),
+            Precision::Milliseconds => time.timestamp_millis(),
+            Precision::Seconds => time.timestamp(),
+        }
+    }
 }
 
 impl InfluxDbClient {
+    /// 检查不需要连接即可确定的配置错误
+    pub fn validate_config(config: &InfluxDbConfig) -> Result<()> {
+        Precision::parse(&config.precision)?;
+        secrets::resolve_optional(config.password.as_deref())?;
+        Ok(())
+    }
+
     pub async fn new(config: &InfluxDbConfig) -> Result<Self> {
         let mut client = Client::new(&config.url, &config.database);
+        let password = secrets::resolve_optional(config.password.as_deref())?;
         
-        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        if let (Some(username), Some(password)) = (&config.username, &password) {
             client = client.with_auth(username, password);
         }
 
         let influx_client = Self {
             client,
+            http: reqwest::Client::new(),
+            url: config.url.trim_end_matches('/').to_string(),
             database: config.database.clone(),
+            username: config.username.clone(),
+            password,
+            precision: Precision::parse(&config.precision)?,
+            retention_policy: config.retention_policy.clone().filter(|rp| !rp.is_empty()),
+            batch_size: config.batch_size.max(1),
+            mapping: PointMapping::new(config),
         };
 
         // 测试连接
@@ -30,27 +409,12 @@ impl InfluxDbClient {
     }
 
     pub async fn write_signal(&self, signal: &DeviceSignal) -> Result<()> {
-        let point = TimeSeriesPoint::from(signal);
+        let point = self.mapping.to_point(signal);
         self.write_point(&point).await
     }
 
     pub async fn write_point(&self, point: &TimeSeriesPoint) -> Result<()> {
-        let mut influx_point = influxdb::WriteQuery::new(
-            Timestamp::Milliseconds(point.timestamp.timestamp_millis() as u128),
-            &point.measurement
-        );
-
-        // 添加标签
-        for (key, value) in &point.tags {
-            influx_point = influx_point.add_tag(key, value.as_str());
-        }
-
-        // 添加字段
-        for (key, value) in &point.fields {
-            influx_point = influx_point.add_field(key, *value);
-        }
-
-        self.client.query(influx_point).await?;
+        self.write_lines(&self.to_line_protocol(point)).await?;
         
         log::debug!("Written point to InfluxDB: {}", point.measurement);
         Ok(())
@@ -61,57 +425,206 @@ impl InfluxDbClient {
             return Ok(());
         }
 
-        let points: Vec<TimeSeriesPoint> = signals.iter().map(TimeSeriesPoint::from).collect();
+        let points: Vec<TimeSeriesPoint> = signals.iter().map(|signal| self.mapping.to_point(signal)).collect();
         self.write_batch_points(&points).await
     }
 
+    /// 批量写入数据点
+    ///
+    /// 每`batch_size`个数据点合并为一个line protocol请求体，通过一次HTTP请求写入。
     pub async fn write_batch_points(&self, points: &[TimeSeriesPoint]) -> Result<()> {
         if points.is_empty() {
             return Ok(());
         }
 
-        let mut queries = Vec::new();
-        
-        for point in points {
-            let mut influx_point = influxdb::WriteQuery::new(
-                Timestamp::Milliseconds(point.timestamp.timestamp_millis() as u128),
-                &point.measurement
-            );
-
-            // 添加标签
-            for (key, value) in &point.tags {
-                influx_point = influx_point.add_tag(key, value.as_str());
-            }
+        for chunk in points.chunks(self.batch_size) {
+            let body = chunk
+                .iter()
+                .map(|point| self.to_line_protocol(point))
+                .collect::<Vec<_>>()
+                .join("\n");
 
-            // 添加字段
-            for (key, value) in &point.fields {
-                influx_point = influx_point.add_field(key, *value);
-            }
+            self.write_lines(&body).await?;
+        }
+
+        log::debug!("Written {} points to InfluxDB", points.len());
+        Ok(())
+    }
 
-            queries.push(influx_point);
+    /// 通过`/write`接口提交line protocol数据
+    async fn write_lines(&self, body: &str) -> Result<()> {
+        let mut params = vec![
+            ("db", self.database.as_str()),
+            ("precision", self.precision.as_param()),
+        ];
+        if let Some(rp) = &self.retention_policy {
+            params.push(("rp", rp.as_str()));
         }
 
-        // 批量写入
-        for query in queries {
-            self.client.query(query).await?;
+        let mut request = self.http
+            .post(format!("{}/write", self.url))
+            .query(&params)
+            .body(body.to_string());
+
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            let error = format!("InfluxDB write failed ({}): {}", status, message.trim());
+            // 400（格式错误、字段类型冲突）和413（请求过大）重试同样的数据不会成功
+            return Err(match status {
+                StatusCode::BAD_REQUEST | StatusCode::PAYLOAD_TOO_LARGE => Rejected(error).into(),
+                _ => anyhow::anyhow!(error),
+            });
         }
 
-        log::debug!("Written {} points to InfluxDB", points.len());
         Ok(())
     }
 
+    /// 将数据点编码为一行line protocol
+    fn to_line_protocol(&self, point: &TimeSeriesPoint) -> String {
+        let mut line = escape_measurement(&point.measurement);
+
+        // 标签按键排序可以提升InfluxDB写入性能
+        let mut tags: Vec<_> = point.tags
+            .iter()
+            .map(|(key, value)| (escape_key(key), escape_key(value)))
+            .filter(|(key, value)| !key.is_empty() && !value.is_empty())
+            .collect();
+        tags.sort_by(|a, b| a.0.cmp(&b.0));
+        for (key, value) in tags {
+            line.push(',');
+            line.push_str(&key);
+            line.push('=');
+            line.push_str(&value);
+        }
+
+        let mut fields: Vec<_> = point.fields.iter().collect();
+        fields.sort_by(|a, b| a.0.cmp(b.0));
+        let fields: Vec<String> = fields
+            .into_iter()
+            .map(|(key, value)| format!("{}={}", escape_key(key), field_value(value)))
+            .collect();
+
+        line.push(' ');
+        line.push_str(&fields.join(","));
+        line.push(' ');
+        line.push_str(&self.precision.timestamp(&point.timestamp).to_string());
+        line
+    }
+
+    /// 查询最近的设备信号
     pub async fn query_recent_signals(&self, device_id: Option<&str>, limit: Option<u32>) -> Result<Vec<DeviceSignal>> {
-        // 注意: InfluxDB 1.8的查询结果解析比较复杂
-        // 这里提供一个基础的框架，实际使用时可能需要根据具体的InfluxDB返回格式进行调整
+        let limit = limit.unwrap_or(100);
+
+        let mut influxql = format!("SELECT * FROM {}", self.from_clause(&[]));
+        if let Some(device_id) = device_id {
+            influxql.push_str(&format!(" WHERE \"device_id\" = {}", quote_literal(device_id)));
+        }
+        // 按全部标签分组，结果的列只包含字段，才能区分多字段信号的字段和标签
+        influxql.push_str(&format!(" GROUP BY * ORDER BY time DESC LIMIT {}", limit));
+
+        let series = self.query(&influxql).await?;
+
         let mut signals = Vec::new();
-        
-        // 由于influxdb crate在查询方面的限制，这里暂时返回空集合
-        // 在实际使用中，您可能需要使用HTTP客户端直接查询InfluxDB API
-        log::warn!("Query functionality requires direct HTTP client implementation for InfluxDB 1.8");
-        
+        for series in &series {
+            signals.extend(series.to_signals(&self.mapping)?);
+        }
+
+        // 每个序列和measurement的结果各自排序，合并后重新按时间倒序截取
+        signals.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        signals.truncate(limit as usize);
+
         Ok(signals)
     }
 
+    /// 按时间范围查询信号，可选按时间窗口聚合
+    pub async fn query_signals(&self, query: &SignalQuery) -> Result<Vec<SignalSeries>> {
+        // 多字段信号的字段名事先未知，选择全部字段后在结果中按字段拆分
+        let select = match query.aggregation {
+            Some((aggregation, _)) => format!("{}(*)", aggregation.as_str()),
+            None => "*".to_string(),
+        };
+        let signal_types = stored_signal_types(&query.signal_types);
+
+        let mut conditions = vec![
+            format!("time >= {}", quote_literal(&query.from.to_rfc3339())),
+            format!("time < {}", quote_literal(&query.to.to_rfc3339())),
+        ];
+        if let Some(condition) = tag_condition("device_id", &query.device_ids) {
+            conditions.push(condition);
+        }
+        if let Some(condition) = tag_condition("signal_type", &signal_types) {
+            conditions.push(condition);
+        }
+
+        let group_by = match query.aggregation {
+            Some((_, window)) => format!("time({}), \"device_id\", \"signal_type\" fill(none)", window.as_str()),
+            None => "\"device_id\", \"signal_type\"".to_string(),
+        };
+
+        let influxql = format!(
+            "SELECT {} FROM {} WHERE {} GROUP BY {} ORDER BY time ASC LIMIT {}",
+            select,
+            self.from_clause(&signal_types),
+            conditions.join(" AND "),
+            group_by,
+            query.limit
+        );
+
+        let mut rows = Vec::new();
+        for series in self.query(&influxql).await? {
+            rows.extend(series.to_series_points(&self.mapping, query)?);
+        }
+
+        Ok(SignalSeries::group(rows))
+    }
+
+    /// 查询涉及的measurement列表
+    fn from_clause(&self, signal_types: &[String]) -> String {
+        self.mapping
+            .measurements_for(signal_types)
+            .into_iter()
+            .map(quote_identifier)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// 通过`/query`接口执行InfluxQL查询
+    pub async fn query(&self, influxql: &str) -> Result<Vec<Series>> {
+        log::debug!("InfluxQL: {}", influxql);
+
+        let mut request = self.http
+            .get(format!("{}/query", self.url))
+            .query(&[("db", self.database.as_str()), ("q", influxql)]);
+
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let body: QueryResponse = response.json().await?;
+
+        if let Some(error) = body.error {
+            return Err(anyhow::anyhow!("InfluxDB query failed ({}): {}", status, error));
+        }
+
+        let mut series = Vec::new();
+        for result in body.results {
+            if let Some(error) = result.error {
+                return Err(anyhow::anyhow!("InfluxDB query failed: {}", error));
+            }
+            series.extend(result.series);
+        }
+
+        Ok(series)
+    }
+
     pub async fn health_check(&self) -> Result<bool> {
         // 尝试查询数据库信息
         let ping_result = self.client.ping().await;
@@ -133,4 +646,200 @@ impl InfluxDbClient {
         log::info!("InfluxDB database '{}' should be created manually or via HTTP API", self.database);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl SignalSink for InfluxDbClient {
+    fn name(&self) -> &'static str {
+        "InfluxDB"
+    }
+
+    async fn write_signal(&self, signal: &DeviceSignal) -> Result<()> {
+        InfluxDbClient::write_signal(self, signal).await
+    }
+
+    async fn write_batch(&self, signals: &[DeviceSignal]) -> Result<()> {
+        self.write_batch_signals(signals).await
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        InfluxDbClient::health_check(self).await
+    }
+}
+
+/// InfluxQL标识符加双引号
+pub fn quote_identifier(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// InfluxQL字符串字面量加单引号
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// 生成`("tag" = 'a' OR "tag" = 'b')`条件，值为空时不过滤
+fn tag_condition(tag: &str, values: &[String]) -> Option<String> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let alternatives: Vec<String> = values
+        .iter()
+        .map(|value| format!("{} = {}", quote_identifier(tag), quote_literal(value)))
+        .collect();
+    Some(format!("({})", alternatives.join(" OR ")))
+}
+
+/// 去掉行协议无法转义的字符
+///
+/// 换行会截断当前行，末尾的反斜杠会转义紧随其后的分隔符，两者都会破坏整批数据。
+fn strip_unescapable(value: &str) -> String {
+    let value: String = value.chars().filter(|c| *c != '\n' && *c != '\r').collect();
+    value.trim_end_matches('\\').to_string()
+}
+
+/// 转义measurement中的逗号和空格
+fn escape_measurement(value: &str) -> String {
+    strip_unescapable(value).replace(',', "\\,").replace(' ', "\\ ")
+}
+
+/// 转义标签键、标签值和字段键中的逗号、等号和空格
+fn escape_key(value: &str) -> String {
+    strip_unescapable(value)
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// 转换为InfluxDB字段值
+///
+/// 同一字段在InfluxDB中只能有一种类型。设备上报的JSON数字是否带小数点并不稳定，
+/// 整数统一按浮点数写入；JSON值序列化为字符串。
+fn field_type(value: &SignalValue) -> SignalValue {
+    match value {
+        SignalValue::Integer(value) => SignalValue::Float(*value as f64),
+        SignalValue::Json(value) => SignalValue::String(value.to_string()),
+        other => other.clone(),
+    }
+}
+
+/// 按InfluxDB字段类型编码字段值
+fn field_value(value: &SignalValue) -> String {
+    match value {
+        SignalValue::Float(value) => value.to_string(),
+        SignalValue::Integer(value) => format!("{}i", value),
+        SignalValue::Boolean(value) => value.to_string(),
+        SignalValue::String(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        SignalValue::Json(value) => format!("\"{}\"", value.to_string().replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Aggregation, AggregationWindow};
+
+    fn mapping() -> PointMapping {
+        PointMapping {
+            measurement: "signals".to_string(),
+            measurements: HashMap::new(),
+            tag_keys: vec!["site".to_string()],
+            field_keys: vec!["rssi".to_string()],
+        }
+    }
+
+    fn series(value: serde_json::Value) -> Series {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn query(signal_types: &[&str], aggregation: Option<(Aggregation, AggregationWindow)>) -> SignalQuery {
+        SignalQuery {
+            device_ids: Vec::new(),
+            signal_types: signal_types.iter().map(|t| t.to_string()).collect(),
+            from: Utc::now() - chrono::Duration::hours(1),
+            to: Utc::now(),
+            aggregation,
+            limit: 100,
+        }
+    }
+
+    #[test]
+    fn restores_multi_field_signals() {
+        let series = series(serde_json::json!({
+            "name": "signals",
+            "tags": {"device_id": "meter-1", "signal_type": "power", "unit": "", "site": "north", "phase": "L1"},
+            "columns": ["time", "current", "rssi", "voltage"],
+            "values": [["2024-05-01T00:00:00Z", 5.2, -70, 230.1]]
+        }));
+
+        let signals = series.to_signals(&mapping()).unwrap();
+        assert_eq!(signals.len(), 1);
+
+        let signal = &signals[0];
+        assert_eq!(signal.device_id, "meter-1");
+        assert_eq!(signal.signal_type, "power");
+        assert!(signal.value.is_none());
+        assert_eq!(signal.unit, None);
+        assert_eq!(signal.fields.len(), 2);
+        assert_eq!(signal.fields["voltage"].as_f64(), Some(230.1));
+        assert_eq!(signal.fields["current"].as_f64(), Some(5.2));
+        assert_eq!(signal.tags.get("phase").map(String::as_str), Some("L1"));
+        assert_eq!(signal.metadata, Some(serde_json::json!({"site": "north", "rssi": -70})));
+    }
+
+    #[test]
+    fn restores_single_value_signals() {
+        let series = series(serde_json::json!({
+            "name": "signals",
+            "tags": {"device_id": "pump-1", "signal_type": "running", "unit": ""},
+            "columns": ["time", "value", "value_bool"],
+            "values": [["2024-05-01T00:00:00Z", null, true], ["2024-05-01T00:01:00Z", null, null]]
+        }));
+
+        let signals = series.to_signals(&mapping()).unwrap();
+        assert_eq!(signals.len(), 1);
+        assert!(matches!(signals[0].value, Some(SignalValue::Boolean(true))));
+        assert!(signals[0].fields.is_empty());
+    }
+
+    #[test]
+    fn splits_multi_field_series_like_mariadb() {
+        let series = series(serde_json::json!({
+            "name": "signals",
+            "tags": {"device_id": "meter-1", "signal_type": "power"},
+            "columns": ["time", "current", "phase", "rssi", "voltage"],
+            "values": [["2024-05-01T00:00:00Z", 5.2, "L1", -70, 230.1]]
+        }));
+
+        let points = series.to_series_points(&mapping(), &query(&[], None)).unwrap();
+        let names: Vec<&str> = points.iter().map(|(_, name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["power.current", "power.voltage"]);
+
+        let points = series.to_series_points(&mapping(), &query(&["power.voltage"], None)).unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].1, "power.voltage");
+        assert_eq!(points[0].2.value, 230.1);
+    }
+
+    #[test]
+    fn strips_aggregation_prefix() {
+        let series = series(serde_json::json!({
+            "name": "signals",
+            "tags": {"device_id": "sensor-1", "signal_type": "temperature"},
+            "columns": ["time", "mean_value"],
+            "values": [["2024-05-01T00:00:00Z", 21.5]]
+        }));
+
+        let aggregation = Some((Aggregation::Mean, AggregationWindow::OneMinute));
+        let points = series.to_series_points(&mapping(), &query(&["temperature"], aggregation)).unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].1, "temperature");
+        assert_eq!(points[0].2.value, 21.5);
+    }
+
+    #[test]
+    fn queries_base_signal_type_for_split_names() {
+        let types = vec!["power.voltage".to_string(), "power.current".to_string(), "temperature".to_string()];
+        assert_eq!(stored_signal_types(&types), vec!["power", "power.current", "power.voltage", "temperature"]);
+    }
+}
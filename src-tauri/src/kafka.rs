@@ -1,29 +1,261 @@
 use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
 use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
 use rdkafka::util::Timeout;
 use anyhow::Result;
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::time::Duration;
 use crate::models::DeviceSignal;
-use crate::config::KafkaConfig;
+use crate::config::{KafkaConfig, KafkaKeyStrategy, KafkaRouteConfig, KafkaSecurityConfig, KAFKA_SECRET_PROPERTIES};
+use crate::secrets;
+use crate::sink::{Rejected, SignalSink};
+
+/// 发送到Kafka的信号消息，附带`value_type`/`field_types`方便下游按类型解析值
+///
+/// 多字段信号作为一条消息发送。
+#[derive(Serialize)]
+struct SignalMessage<'a> {
+    #[serde(flatten)]
+    signal: &'a DeviceSignal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    field_types: BTreeMap<&'a str, &'static str>,
+}
+
+impl<'a> SignalMessage<'a> {
+    fn encode(signal: &'a DeviceSignal) -> Result<String> {
+        Ok(serde_json::to_string(&SignalMessage {
+            signal,
+            value_type: signal.value.as_ref().map(|value| value.type_name()),
+            field_types: signal.fields
+                .iter()
+                .map(|(field, value)| (field.as_str(), value.type_name()))
+                .collect(),
+        })?)
+    }
+}
+
+/// 按`key_strategy`生成消息键，`None`表示不设置键
+///
+/// 带幂等键的信号同样按配置的策略生成键，以保持分区内的顺序；需要按键去重时使用`Id`策略。
+fn message_key(strategy: KafkaKeyStrategy, signal: &DeviceSignal) -> Option<String> {
+    match strategy {
+        KafkaKeyStrategy::Composite => Some(format!("{}_{}", signal.device_id, signal.signal_type)),
+        KafkaKeyStrategy::Device => Some(signal.device_id.clone()),
+        KafkaKeyStrategy::Id => signal.id.map(|id| id.to_string()),
+        KafkaKeyStrategy::None => None,
+    }
+}
+
+/// 消息过大或格式无效，重试同样的消息不会成功
+fn is_rejection(error: &KafkaError) -> bool {
+    matches!(
+        error.rdkafka_error_code(),
+        Some(RDKafkaErrorCode::MessageSizeTooLarge | RDKafkaErrorCode::InvalidMessageSize | RDKafkaErrorCode::InvalidRecord)
+    )
+}
+
+/// 按信号类型、设备ID前缀和标签/metadata选择主题
+#[derive(Clone)]
+struct TopicRouter {
+    default_topic: String,
+    routes: Vec<KafkaRouteConfig>,
+}
+
+impl TopicRouter {
+    fn new(config: &KafkaConfig) -> Result<Self> {
+        for (index, route) in config.routes.iter().enumerate() {
+            if route.topic.trim().is_empty() {
+                return Err(anyhow::anyhow!("Kafka route #{} has no topic", index + 1));
+            }
+            if route.signal_type.is_none() && route.device_prefix.is_none() && route.metadata_key.is_none() {
+                log::warn!("Kafka route #{} has no conditions and matches every signal", index + 1);
+            }
+        }
+
+        Ok(Self {
+            default_topic: config.topic.clone(),
+            routes: config.routes.clone(),
+        })
+    }
+
+    fn topic(&self, signal: &DeviceSignal) -> &str {
+        self.routes
+            .iter()
+            .find(|route| Self::matches(route, signal))
+            .map(|route| route.topic.as_str())
+            .unwrap_or(&self.default_topic)
+    }
+
+    fn matches(route: &KafkaRouteConfig, signal: &DeviceSignal) -> bool {
+        if let Some(pattern) = &route.signal_type {
+            let matched = match pattern.strip_suffix('*') {
+                Some(prefix) => signal.signal_type.starts_with(prefix),
+                None => signal.signal_type == *pattern,
+            };
+            if !matched {
+                return false;
+            }
+        }
+
+        if let Some(prefix) = &route.device_prefix {
+            if !signal.device_id.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+
+        if let Some(key) = &route.metadata_key {
+            // 标签优先，其次是metadata中的顶层键
+            let value = signal.tags.get(key).cloned().or_else(|| {
+                signal.metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata.get(key))
+                    .map(|value| match value {
+                        serde_json::Value::String(text) => text.clone(),
+                        other => other.to_string(),
+                    })
+            });
+
+            match (value, &route.metadata_value) {
+                (None, _) => return false,
+                (Some(value), Some(expected)) if value != *expected => return false,
+                _ => {},
+            }
+        }
+
+        true
+    }
+}
+
+/// 设置确认级别、幂等性、压缩和凑批参数
+fn apply_tuning(client_config: &mut ClientConfig, config: &KafkaConfig) -> Result<()> {
+    let acks = match config.acks.trim().to_lowercase().as_str() {
+        "all" | "-1" => "all",
+        "1" => "1",
+        "0" => "0",
+        other => return Err(anyhow::anyhow!("Unsupported Kafka acks setting: {}", other)),
+    };
+    if config.enable_idempotence && acks != "all" {
+        return Err(anyhow::anyhow!("kafka.enable_idempotence requires kafka.acks = \"all\""));
+    }
+
+    let compression = config.compression.trim().to_lowercase();
+    if !matches!(compression.as_str(), "none" | "gzip" | "snappy" | "lz4" | "zstd") {
+        return Err(anyhow::anyhow!("Unsupported Kafka compression: {}", config.compression));
+    }
+
+    client_config
+        .set("acks", acks)
+        .set("enable.idempotence", config.enable_idempotence.to_string())
+        .set("compression.type", &compression)
+        .set("linger.ms", config.linger_ms.to_string())
+        .set("batch.num.messages", config.batch_size.max(1).to_string())
+        .set("queue.buffering.max.messages", config.queue_max_messages.max(1).to_string())
+        .set("message.timeout.ms", config.message_timeout_ms.to_string());
+
+    Ok(())
+}
+
+/// 设置安全协议、SASL认证和TLS证书，生产者和消费者共用
+pub fn apply_security(client_config: &mut ClientConfig, config: &KafkaSecurityConfig) -> Result<()> {
+    let protocol = config.security_protocol.trim().to_lowercase();
+    if !matches!(protocol.as_str(), "plaintext" | "ssl" | "sasl_plaintext" | "sasl_ssl") {
+        return Err(anyhow::anyhow!("Unsupported Kafka security protocol: {}", config.security_protocol));
+    }
+    client_config.set("security.protocol", &protocol);
+
+    if protocol.starts_with("sasl_") {
+        let mechanism = config.sasl_mechanism.as_deref().unwrap_or("PLAIN").trim().to_uppercase();
+        if !matches!(mechanism.as_str(), "PLAIN" | "SCRAM-SHA-256" | "SCRAM-SHA-512") {
+            return Err(anyhow::anyhow!("Unsupported Kafka SASL mechanism: {}", mechanism));
+        }
+
+        let username = config.sasl_username.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("sasl_username is required for {}", protocol))?;
+        let password = secrets::resolve_optional(config.sasl_password.as_deref())?
+            .ok_or_else(|| anyhow::anyhow!("sasl_password is required for {}", protocol))?;
+
+        client_config
+            .set("sasl.mechanism", &mechanism)
+            .set("sasl.username", username)
+            .set("sasl.password", &password);
+    }
+
+    if protocol.ends_with("ssl") {
+        let locations = [
+            ("ssl.ca.location", &config.ssl_ca_location),
+            ("ssl.certificate.location", &config.ssl_certificate_location),
+            ("ssl.key.location", &config.ssl_key_location),
+        ];
+        for (key, value) in locations {
+            if let Some(value) = value.as_deref().filter(|value| !value.is_empty()) {
+                client_config.set(key, value);
+            }
+        }
+        if let Some(password) = secrets::resolve_optional(config.ssl_key_password.as_deref())? {
+            client_config.set("ssl.key.password", &password);
+        }
+    }
+
+    Ok(())
+}
 
 #[derive(Clone)]
 pub struct KafkaProducer {
     producer: FutureProducer,
+    /// 默认主题，用于健康检查和测试消息
     topic: String,
+    router: TopicRouter,
+    key_strategy: KafkaKeyStrategy,
+    /// 本地队列已满时等待入队的时间
+    send_timeout: Duration,
 }
 
 impl KafkaProducer {
-    pub async fn new(config: &KafkaConfig) -> Result<Self> {
-        let producer: FutureProducer = ClientConfig::new()
+    /// 检查不需要连接即可确定的配置错误
+    pub fn validate_config(config: &KafkaConfig) -> Result<()> {
+        Self::client_config(config)?;
+        TopicRouter::new(config)?;
+        Ok(())
+    }
+
+    fn client_config(config: &KafkaConfig) -> Result<ClientConfig> {
+        let mut client_config = ClientConfig::new();
+        client_config
             .set("bootstrap.servers", &config.brokers)
             .set("client.id", &config.client_id)
-            .set("message.timeout.ms", "5000")
-            .set("queue.buffering.max.messages", "10000")
-            .set("queue.buffering.max.ms", "0")
-            .set("batch.num.messages", "1000")
             .set("socket.timeout.ms", "3000")  // 减少超时时间
-            .set("metadata.request.timeout.ms", "3000")  // 减少元数据请求超时
-            .create()?;
+            .set("metadata.request.timeout.ms", "3000");  // 减少元数据请求超时
+        apply_tuning(&mut client_config, config)?;
+        apply_security(&mut client_config, &config.security)?;
+
+        for (key, value) in &config.extra {
+            if KAFKA_SECRET_PROPERTIES.contains(&key.as_str()) {
+                client_config.set(key, secrets::resolve(value)?);
+            } else {
+                client_config.set(key, value);
+            }
+        }
+        Ok(client_config)
+    }
+
+    pub async fn new(config: &KafkaConfig) -> Result<Self> {
+        let client_config = Self::client_config(config)?;
+        log::info!(
+            "Kafka producer: acks={}, idempotence={}, compression={}, linger={}ms, batch={}",
+            config.acks, config.enable_idempotence, config.compression, config.linger_ms, config.batch_size
+        );
+        if !config.extra.is_empty() {
+            let mut keys: Vec<&str> = config.extra.keys().map(|key| key.as_str()).collect();
+            keys.sort();
+            log::info!("Kafka producer extra properties: {}", keys.join(", "));
+        }
+
+        let producer: FutureProducer = client_config.create()?;
 
         // 测试连接 - 使用更短的超时时间
         match producer.client().fetch_metadata(None, Timeout::After(Duration::from_secs(3))) {
@@ -41,31 +273,45 @@ impl KafkaProducer {
         Ok(Self {
             producer,
             topic: config.topic.clone(),
+            router: TopicRouter::new(config)?,
+            key_strategy: config.key_strategy,
+            send_timeout: Duration::from_millis(config.send_timeout_ms),
         })
     }
 
+    /// 按路由规则和键策略创建消息
+    fn record<'a>(&'a self, signal: &DeviceSignal, key: &'a Option<String>, payload: &'a str) -> FutureRecord<'a, str, str> {
+        let record = FutureRecord::to(self.router.topic(signal)).payload(payload);
+        match key {
+            Some(key) => record.key(key.as_str()),
+            None => record,
+        }
+    }
+
     pub async fn send_signal(&self, signal: &DeviceSignal) -> Result<()> {
-        let key = format!("{}_{}", signal.device_id, signal.signal_type);
-        let payload = serde_json::to_string(signal)?;
+        let key = message_key(self.key_strategy, signal);
+        let payload = SignalMessage::encode(signal)?;
+        let topic = self.router.topic(signal);
 
-        let record = FutureRecord::to(&self.topic)
-            .key(&key)
-            .payload(&payload);
+        let record = self.record(signal, &key, &payload);
 
         let delivery_status = self.producer
-            .send(record, Timeout::After(Duration::from_secs(5)))
+            .send(record, Timeout::After(self.send_timeout))
             .await;
 
         match delivery_status {
             Ok((partition, offset)) => {
                 log::debug!(
                     "Message sent to Kafka: topic={}, partition={}, offset={}, device_id={}", 
-                    self.topic, partition, offset, signal.device_id
+                    topic, partition, offset, signal.device_id
                 );
                 Ok(())
             },
             Err((kafka_error, _)) => {
                 log::error!("Failed to send message to Kafka: {}", kafka_error);
+                if is_rejection(&kafka_error) {
+                    return Err(Rejected(format!("Kafka rejected message: {}", kafka_error)).into());
+                }
                 Err(kafka_error.into())
             }
         }
@@ -76,24 +322,32 @@ impl KafkaProducer {
             return Ok(());
         }
 
-        let mut success_count = 0;
-        let mut error_count = 0;
+        let messages = signals
+            .iter()
+            .map(|signal| Ok((message_key(self.key_strategy, signal), SignalMessage::encode(signal)?)))
+            .collect::<Result<Vec<(Option<String>, String)>>>()?;
 
-        for signal in signals {
-            let key = format!("{}_{}", signal.device_id, signal.signal_type);
-            let payload = serde_json::to_string(signal)?;
+        // 所有消息先进入生产者队列再统一等待确认，由librdkafka按linger/batch设置凑批发送
+        let deliveries = signals.iter().zip(&messages).map(|(signal, (key, payload))| {
+            let record = self.record(signal, key, payload);
+            self.producer.send(record, Timeout::After(self.send_timeout))
+        });
 
-            let record = FutureRecord::to(&self.topic)
-                .key(&key)
-                .payload(&payload);
+        let mut success_count = 0;
+        let mut error_count = 0;
+        let mut rejected_count = 0;
 
-            match self.producer.send(record, Timeout::After(Duration::from_secs(5))).await {
+        for delivery in join_all(deliveries).await {
+            match delivery {
                 Ok((partition, offset)) => {
                     success_count += 1;
                     log::debug!("Batch message sent: partition={}, offset={}", partition, offset);
                 },
                 Err((kafka_error, _)) => {
                     error_count += 1;
+                    if is_rejection(&kafka_error) {
+                        rejected_count += 1;
+                    }
                     log::error!("Failed to send batch message: {}", kafka_error);
                 }
             }
@@ -101,7 +355,9 @@ impl KafkaProducer {
 
         log::info!("Batch send completed: {} success, {} errors", success_count, error_count);
 
-        if error_count > 0 {
+        if error_count > 0 && rejected_count == error_count {
+            Err(Rejected(format!("Kafka rejected {} out of {} messages", error_count, signals.len())).into())
+        } else if error_count > 0 {
             Err(anyhow::anyhow!("Failed to send {} out of {} messages", error_count, signals.len()))
         } else {
             Ok(())
@@ -114,7 +370,7 @@ impl KafkaProducer {
             .payload(payload);
 
         let delivery_status = self.producer
-            .send(record, Timeout::After(Duration::from_secs(5)))
+            .send(record, Timeout::After(self.send_timeout))
             .await;
 
         match delivery_status {
@@ -169,4 +425,67 @@ impl KafkaProducer {
         log::debug!("Kafka producer flushed");
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl SignalSink for KafkaProducer {
+    fn name(&self) -> &'static str {
+        "Kafka"
+    }
+
+    async fn write_signal(&self, signal: &DeviceSignal) -> Result<()> {
+        self.send_signal(signal).await
+    }
+
+    async fn write_batch(&self, signals: &[DeviceSignal]) -> Result<()> {
+        self.send_batch_signals(signals).await
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        KafkaProducer::health_check(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::BTreeMap;
+    use uuid::Uuid;
+
+    fn signal(idempotency_key: Option<&str>) -> DeviceSignal {
+        DeviceSignal {
+            id: Some(Uuid::new_v4()),
+            idempotency_key: idempotency_key.map(str::to_string),
+            device_id: "pump-1".to_string(),
+            signal_type: "temperature".to_string(),
+            value: Some(23.5.into()),
+            fields: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            unit: None,
+            timestamp: Utc::now(),
+            received_at: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn message_key_follows_strategy() {
+        let signal = signal(None);
+
+        assert_eq!(message_key(KafkaKeyStrategy::Composite, &signal).as_deref(), Some("pump-1_temperature"));
+        assert_eq!(message_key(KafkaKeyStrategy::Device, &signal).as_deref(), Some("pump-1"));
+        assert_eq!(message_key(KafkaKeyStrategy::Id, &signal), signal.id.map(|id| id.to_string()));
+        assert_eq!(message_key(KafkaKeyStrategy::None, &signal), None);
+    }
+
+    #[test]
+    fn message_key_honors_strategy_for_idempotent_signals() {
+        let signal = signal(Some("reading-42"));
+
+        assert_eq!(message_key(KafkaKeyStrategy::Composite, &signal).as_deref(), Some("pump-1_temperature"));
+        assert_eq!(message_key(KafkaKeyStrategy::Device, &signal).as_deref(), Some("pump-1"));
+        assert_eq!(message_key(KafkaKeyStrategy::Id, &signal), signal.id.map(|id| id.to_string()));
+        assert_eq!(message_key(KafkaKeyStrategy::None, &signal), None);
+    }
+}
@@ -0,0 +1,153 @@
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::Message;
+use rdkafka::{Offset, TopicPartitionList};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use crate::models::{DeviceSignal, DeviceSignalPayload};
+use crate::config::KafkaConsumerConfig;
+use crate::kafka;
+use crate::collector;
+use crate::services::SharedDataService;
+
+/// Kafka信号消费者
+///
+/// 订阅设备直接发布的主题，解码`DeviceSignalRequest`载荷后批量写入数据服务。
+/// 偏移量只在整批信号持久化成功后提交，保证至少一次投递。
+pub struct KafkaConsumer {
+    consumer: StreamConsumer,
+    config: KafkaConsumerConfig,
+}
+
+impl KafkaConsumer {
+    pub fn new(config: &KafkaConsumerConfig) -> Result<Self> {
+        let mut client_config = ClientConfig::new();
+        client_config
+            .set("bootstrap.servers", &config.brokers)
+            .set("group.id", &config.group_id)
+            .set("client.id", &config.client_id)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", &config.auto_offset_reset)
+            .set("session.timeout.ms", "10000");
+        kafka::apply_security(&mut client_config, &config.security)?;
+
+        let consumer: StreamConsumer = client_config.create()?;
+
+        let topics: Vec<&str> = config.topics.iter().map(|t| t.as_str()).collect();
+        consumer.subscribe(&topics)?;
+
+        log::info!("Kafka consumer subscribed to {:?} (group: {})", config.topics, config.group_id);
+
+        Ok(Self {
+            consumer,
+            config: config.clone(),
+        })
+    }
+
+    /// 持续消费消息直到任务被取消
+    pub async fn run(self, data_service: SharedDataService) {
+        let batch_timeout = Duration::from_millis(self.config.batch_timeout_ms.max(1));
+
+        loop {
+            let (signals, offsets) = self.next_batch(&data_service, batch_timeout).await;
+            if offsets.is_empty() {
+                continue;
+            }
+
+            if !signals.is_empty() {
+                collector::persist("Kafka", &data_service, &signals).await;
+            }
+
+            if let Err(e) = self.commit(&offsets) {
+                log::error!("Failed to commit Kafka consumer offsets: {}", e);
+            }
+        }
+    }
+
+    /// 收集一批消息，达到`batch_size`或超时后返回
+    async fn next_batch(
+        &self,
+        data_service: &SharedDataService,
+        batch_timeout: Duration,
+    ) -> (Vec<DeviceSignal>, HashMap<(String, i32), i64>) {
+        let ingest = data_service.current().config().ingest.clone();
+        let mut signals = Vec::new();
+        let mut offsets = HashMap::new();
+        let deadline = tokio::time::Instant::now() + batch_timeout;
+
+        while signals.len() < self.config.batch_size.max(1) {
+            let message = match tokio::time::timeout_at(deadline, self.consumer.recv()).await {
+                Ok(Ok(message)) => message,
+                Ok(Err(e)) => {
+                    log::warn!("Kafka consumer error: {}", e);
+                    break;
+                },
+                Err(_) => break,
+            };
+
+            offsets.insert((message.topic().to_string(), message.partition()), message.offset());
+
+            let payload = match message.payload() {
+                Some(payload) => payload,
+                None => {
+                    log::warn!("Skipping empty Kafka message at {}/{}@{}", message.topic(), message.partition(), message.offset());
+                    continue;
+                }
+            };
+
+            // 无法解析的消息会被跳过并随批次提交，避免阻塞整个分区
+            match serde_json::from_slice::<DeviceSignalPayload>(payload) {
+                Ok(payload) => {
+                    for request in payload.into_requests() {
+                        match request.into_signal(&ingest) {
+                            Ok(signal) => signals.push(signal),
+                            Err(e) => log::warn!(
+                                "Skipping invalid signal at {}/{}@{}: {}",
+                                message.topic(), message.partition(), message.offset(), e
+                            ),
+                        }
+                    }
+                },
+                Err(e) => log::warn!(
+                    "Skipping undecodable Kafka message at {}/{}@{}: {}",
+                    message.topic(), message.partition(), message.offset(), e
+                ),
+            }
+        }
+
+        (signals, offsets)
+    }
+
+    fn commit(&self, offsets: &HashMap<(String, i32), i64>) -> Result<()> {
+        let mut list = TopicPartitionList::new();
+        for ((topic, partition), offset) in offsets {
+            // 提交的是下一条待消费消息的偏移量
+            list.add_partition_offset(topic, *partition, Offset::Offset(offset + 1))?;
+        }
+
+        self.consumer.commit(&list, CommitMode::Async)?;
+        Ok(())
+    }
+}
+
+/// 按配置启动Kafka消费者后台任务，返回的任务终止时消费者随之关闭
+pub fn spawn(config: &KafkaConsumerConfig, data_service: SharedDataService) -> Vec<JoinHandle<()>> {
+    if !config.enabled {
+        return Vec::new();
+    }
+
+    if config.topics.is_empty() {
+        log::warn!("Kafka consumer enabled but no topics configured");
+        return Vec::new();
+    }
+
+    match KafkaConsumer::new(config) {
+        Ok(consumer) => vec![tokio::spawn(consumer.run(data_service))],
+        Err(e) => {
+            log::error!("❌ Failed to start Kafka consumer: {}", e);
+            Vec::new()
+        }
+    }
+}
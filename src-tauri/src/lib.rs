@@ -1,18 +1,34 @@
-use std::sync::Arc;
+use std::collections::HashMap;
 use tokio::net::TcpListener;
 use anyhow::Result;
 
 // 模块声明
 mod models;
+mod validation;
+mod secrets;
 mod config;
+mod connection;
+mod buffer;
+mod dedup;
 mod mariadb;
 mod influxdb;
 mod kafka;
+mod kafka_consumer;
+mod mqtt;
+mod modbus;
+mod opcua_client;
+mod sink;
 mod services;
+mod collector;
+mod error;
+mod auth;
 mod api;
+mod tls;
 
 use config::AppConfig;
-use services::DataService;
+use connection::ConnectionStatus;
+use services::{DataService, SharedDataService};
+use collector::Collectors;
 use api::{create_router, AppState};
 
 // Tauri commands
@@ -23,7 +39,7 @@ fn greet(name: &str) -> String {
 
 #[tauri::command]
 async fn get_api_status(state: tauri::State<'_, AppState>) -> Result<String, String> {
-    match state.full_health_check().await {
+    match state.current().full_health_check().await {
         Ok(_) => Ok("All systems operational".to_string()),
         Err(e) => Err(format!("System error: {}", e)),
     }
@@ -31,59 +47,51 @@ async fn get_api_status(state: tauri::State<'_, AppState>) -> Result<String, Str
 
 #[tauri::command]
 async fn get_latest_device_signals(state: tauri::State<'_, AppState>, limit: Option<i32>) -> Result<Vec<models::DeviceSignal>, String> {
-    match state.get_latest_signals(limit).await {
+    match state.current().get_latest_signals(limit).await {
         Ok(signals) => Ok(signals),
         Err(e) => Err(format!("Failed to get signals: {}", e)),
     }
 }
 
+#[tauri::command]
+async fn get_timeseries_signals(state: tauri::State<'_, AppState>, device_id: Option<String>, limit: Option<u32>) -> Result<Vec<models::DeviceSignal>, String> {
+    match state.current().query_recent_signals(device_id.as_deref(), limit).await {
+        Ok(signals) => Ok(signals),
+        Err(e) => Err(format!("Failed to query InfluxDB signals: {}", e)),
+    }
+}
+
+#[tauri::command]
+async fn get_connection_status(state: tauri::State<'_, AppState>) -> Result<HashMap<String, ConnectionStatus>, String> {
+    Ok(state.current().connection_status())
+}
+
 #[tauri::command]
 async fn test_connection(state: tauri::State<'_, AppState>, service: String) -> Result<bool, String> {
-    match service.as_str() {
-        "mariadb" => {
-            match state.mariadb_health_check().await {
-                Ok(healthy) => Ok(healthy),
-                Err(e) => Err(format!("MariaDB test failed: {}", e)),
-            }
-        },
-        "influxdb" => {
-            match state.influxdb_health_check().await {
-                Ok(healthy) => Ok(healthy),
-                Err(e) => Err(format!("InfluxDB test failed: {}", e)),
-            }
-        },
-        "kafka" => {
-            match state.kafka_health_check().await {
-                Ok(healthy) => Ok(healthy),
-                Err(e) => Err(format!("Kafka test failed: {}", e)),
-            }
-        },
-        _ => Err("Unknown service".to_string())
+    match state.current().health_check(&service).await {
+        Ok(healthy) => Ok(healthy),
+        Err(e) => Err(format!("{} test failed: {}", service, e)),
     }
 }
 
 #[tauri::command]
 async fn send_test_signal(state: tauri::State<'_, AppState>, signal: models::DeviceSignalRequest) -> Result<String, String> {
-    let device_signal = models::DeviceSignal {
-        id: Some(uuid::Uuid::new_v4()),
-        device_id: signal.device_id,
-        signal_type: signal.signal_type,
-        value: signal.value,
-        unit: signal.unit,
-        timestamp: chrono::Utc::now(),
-        metadata: signal.metadata,
-    };
+    let data_service = state.current();
+    let device_signal = signal
+        .into_signal(&data_service.config().ingest)
+        .map_err(|e| format!("Invalid signal: {}", e))?;
     
-    match state.process_signal(&device_signal).await {
+    match data_service.process_signal(&device_signal).await {
         Ok(_) => Ok("Signal sent successfully".to_string()),
         Err(e) => Err(format!("Failed to send signal: {}", e)),
     }
 }
 
+/// 返回给前端的配置不包含密码明文
 #[tauri::command]
 async fn get_current_config() -> Result<config::AppConfig, String> {
     match config::AppConfig::new() {
-        Ok(config) => Ok(config),
+        Ok(config) => Ok(config.redacted()),
         Err(e) => Err(format!("Failed to load config: {}", e)),
     }
 }
@@ -95,8 +103,14 @@ async fn get_config_file_path() -> Result<String, String> {
 }
 
 #[tauri::command]
-async fn save_config(config: config::AppConfig) -> Result<String, String> {
+async fn save_config(mut config: config::AppConfig) -> Result<String, String> {
     let config_path = config::AppConfig::get_config_path();
+
+    // 未修改的密码以占位符提交，保存前恢复为文件中的原值
+    match config::AppConfig::new() {
+        Ok(previous) => config.restore_redacted(&previous),
+        Err(e) => log::warn!("Failed to load previous configuration, redacted secrets cannot be restored: {}", e),
+    }
     
     match config.save_to_file(&config_path) {
         Ok(_) => {
@@ -111,23 +125,112 @@ async fn save_config(config: config::AppConfig) -> Result<String, String> {
 }
 
 #[tauri::command]
-async fn reload_services(state: tauri::State<'_, AppState>, config: config::AppConfig) -> Result<String, String> {
-    // 注意：这里我们不能直接替换整个DataService，因为Tauri的State是不可变的
-    // 实际实现中，我们需要重新设计架构来支持动态重连
-    // 目前先返回一个提示，建议重启应用
-    Ok("Configuration updated. Please restart the application to apply changes.".to_string())
+async fn reload_services(
+    state: tauri::State<'_, AppState>,
+    collectors: tauri::State<'_, std::sync::Mutex<Collectors>>,
+    mut config: config::AppConfig,
+) -> Result<String, String> {
+    let current = state.current();
+    config.restore_redacted(current.config());
+
+    // 监听地址、TLS和请求体大小限制在服务器启动时确定，修改后仍需重启才能生效
+    let restart_required = config.restart_required(current.config());
+    drop(current);
+
+    match state.reload(&config).await {
+        Ok(_) => {
+            let restarted = match collectors.lock() {
+                Ok(mut collectors) => collectors.reload(&config),
+                Err(poisoned) => poisoned.into_inner().reload(&config),
+            };
+
+            log::info!("✅ Services reloaded with new configuration");
+            let mut message = "Services reloaded successfully.".to_string();
+            if !restarted.is_empty() {
+                message.push_str(&format!(" Restarted ingestion: {}.", restarted.join(", ")));
+            }
+            if !restart_required.is_empty() {
+                message.push_str(&format!(
+                    " Changes to {} take effect after restarting the application.",
+                    restart_required.join(", ")
+                ));
+            }
+            Ok(message)
+        },
+        Err(e) => {
+            log::error!("❌ Failed to reload services: {}", e);
+            Err(format!("Failed to reload services: {}", e))
+        }
+    }
+}
+
+#[tauri::command]
+async fn create_api_key(
+    state: tauri::State<'_, AppState>,
+    name: String,
+    scopes: Vec<auth::Scope>,
+    device_id: Option<String>,
+) -> Result<auth::CreatedApiKey, String> {
+    match state.current().create_api_key(&name, device_id, scopes).await {
+        Ok(created) => Ok(created),
+        Err(e) => Err(format!("Failed to create API key: {}", e)),
+    }
+}
+
+#[tauri::command]
+async fn list_api_keys(state: tauri::State<'_, AppState>) -> Result<Vec<auth::ApiKey>, String> {
+    match state.current().list_api_keys().await {
+        Ok(keys) => Ok(keys),
+        Err(e) => Err(format!("Failed to list API keys: {}", e)),
+    }
+}
+
+#[tauri::command]
+async fn revoke_api_key(state: tauri::State<'_, AppState>, id: String) -> Result<String, String> {
+    match state.current().revoke_api_key(&id).await {
+        Ok(true) => Ok(format!("API key {} revoked", id)),
+        Ok(false) => Err(format!("API key {} not found or already revoked", id)),
+        Err(e) => Err(format!("Failed to revoke API key: {}", e)),
+    }
+}
+
+/// 加密密码，返回可直接写入配置文件的`enc:`值
+#[tauri::command]
+async fn encrypt_secret(value: String) -> Result<String, String> {
+    secrets::encrypt(&value).map_err(|e| format!("Failed to encrypt secret: {}", e))
 }
 
-/// 启动HTTP API服务器
-async fn start_api_server(data_service: Arc<DataService>, config: &AppConfig) -> Result<()> {
-    let app = create_router(data_service.clone());
+/// 启动HTTP API服务器，`server.tls_enabled`时使用HTTPS
+async fn start_api_server(data_service: SharedDataService, config: &AppConfig) -> Result<()> {
+    let app = create_router(data_service);
     
     let addr = format!("{}:{}", config.server.host, config.server.port);
+    let scheme = if config.server.tls_enabled { "https" } else { "http" };
+
+    log::info!("🚀 API Server starting on {}://{}", scheme, addr);
+    log::info!("📡 Ready to receive device signals at {}://{}/api/signals", scheme, addr);
+    if !config.auth.enabled && !matches!(config.server.host.as_str(), "127.0.0.1" | "localhost" | "::1") {
+        log::warn!("⚠️  API authentication is disabled while listening on {}; set auth.enabled = true", addr);
+    }
+
+    if config.server.tls_enabled {
+        let tls = tls::load(&config.server)?;
+        if config.server.tls_client_ca_path.is_some() {
+            log::info!("🔐 Mutual TLS enabled, client certificates required: {}", config.server.tls_client_auth_required);
+        }
+        if config.server.tls_reload_interval_secs > 0 {
+            tokio::spawn(tls::watch(config.server.clone(), tls.clone()));
+        }
+
+        let listener = std::net::TcpListener::bind(&addr)?;
+        listener.set_nonblocking(true)?;
+        axum_server::from_tcp_rustls(listener, tls)
+            .serve(app.into_make_service())
+            .await?;
+        return Ok(());
+    }
+
     let listener = TcpListener::bind(&addr).await?;
-    
-    log::info!("🚀 API Server starting on http://{}", addr);
-    log::info!("📡 Ready to receive device signals at http://{}/api/signals", addr);
-    
     axum::serve(listener, app).await?;
     
     Ok(())
@@ -146,28 +249,29 @@ pub fn run() {
     let rt = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
     
     // 在运行时中初始化服务
-    let data_service = rt.block_on(async {
+    let (data_service, collectors) = rt.block_on(async {
         log::info!("📋 Loading configuration...");
         let config = AppConfig::new().expect("Failed to load configuration");
         
         log::info!("🔌 Initializing data services...");
-        let data_service = match DataService::new(&config).await {
-            Ok(service) => {
-                log::info!("✅ Data services initialized successfully");
-                service
-            },
-            Err(e) => {
-                log::error!("❌ Failed to initialize data services: {}", e);
-                log::warn!("   📝 Please check your configuration in config.toml:");
-                log::warn!("   - MariaDB: Ensure server is running and credentials are correct");
-                log::warn!("   - InfluxDB: Ensure server is running on http://localhost:8086");
-                log::warn!("   - Kafka: Ensure server is running on localhost:9092");
-                log::warn!("   🚀 Application will start but external services may not work");
-                
-                // 返回错误而不是直接退出，让用户可以查看界面
-                std::process::exit(1);
-            }
-        };
+        let data_service = DataService::new(&config).await;
+        let mut disconnected: Vec<String> = data_service.connection_status()
+            .into_iter()
+            .filter(|(_, status)| *status == ConnectionStatus::Disconnected)
+            .map(|(name, _)| name)
+            .collect();
+        disconnected.sort();
+
+        if disconnected.is_empty() {
+            log::info!("✅ Data services initialized successfully");
+        } else {
+            log::warn!("⚠️  Starting in degraded mode, disconnected: {}", disconnected.join(", "));
+            log::warn!("   📝 Please check your configuration in config.toml:");
+            log::warn!("   - MariaDB: Ensure server is running and credentials are correct");
+            log::warn!("   - InfluxDB: Ensure server is running on http://localhost:8086");
+            log::warn!("   - Kafka: Ensure server is running on localhost:9092");
+            log::warn!("   🔁 Unreachable services are retried in the background, invalid configuration is not");
+        }
         
         log::info!("🏥 Performing initial health check...");
         if let Err(e) = data_service.full_health_check().await {
@@ -176,10 +280,13 @@ pub fn run() {
             log::warn!("   Please check your database and Kafka configurations in config.toml");
         }
         
-        let data_service_arc = Arc::new(data_service);
+        let shared_data_service = SharedDataService::new(data_service);
+
+        // 启动数据采集任务
+        let collectors = Collectors::start(&config, shared_data_service.clone());
         
         // 启动API服务器（在后台运行）
-        let server_data_service = data_service_arc.clone();
+        let server_data_service = shared_data_service.clone();
         let server_config = config.clone();
         tokio::spawn(async move {
             if let Err(e) = start_api_server(server_data_service, &server_config).await {
@@ -187,7 +294,7 @@ pub fn run() {
             }
         });
         
-        data_service_arc
+        (shared_data_service, collectors)
     });
 
     log::info!("🖥️  Starting Tauri GUI...");
@@ -196,16 +303,23 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .manage(data_service)
+        .manage(std::sync::Mutex::new(collectors))
         .invoke_handler(tauri::generate_handler![
             greet,
             get_api_status,
             get_latest_device_signals,
+            get_timeseries_signals,
+            get_connection_status,
             test_connection,
             send_test_signal,
             get_current_config,
             get_config_file_path,
             save_config,
-            reload_services
+            reload_services,
+            create_api_key,
+            list_api_keys,
+            revoke_api_key,
+            encrypt_secret
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
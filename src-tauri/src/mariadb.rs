@@ -1,26 +1,49 @@
-use sqlx::{MySqlPool, Row};
+use sqlx::mysql::{MySqlConnectOptions, MySqlDatabaseError};
+use sqlx::{MySql, MySqlPool, QueryBuilder, Row};
 use anyhow::Result;
+use async_trait::async_trait;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
-use crate::models::DeviceSignal;
+use crate::auth::{ApiKey, Scope, StoredApiKey};
+use crate::models::{Aggregation, DeviceSignal, SeriesPoint, SignalQuery, SignalSeries, SignalValue};
 use crate::config::MariaDbConfig;
+use crate::secrets;
+use crate::sink::{Rejected, SignalSink};
+
+/// 每条信号绑定的参数个数
+const INSERT_COLUMNS: usize = 11;
+/// MySQL单条语句最多65535个占位符
+const MAX_BATCH_ROWS: usize = u16::MAX as usize / INSERT_COLUMNS;
 
 #[derive(Clone)]
 pub struct MariaDbClient {
     pool: MySqlPool,
+    batch_size: usize,
 }
 
 impl MariaDbClient {
+    /// 检查不需要连接即可确定的配置错误
+    pub fn validate_config(config: &MariaDbConfig) -> Result<()> {
+        secrets::resolve(&config.password)?;
+        Ok(())
+    }
+
     pub async fn new(config: &MariaDbConfig) -> Result<Self> {
-        let database_url = format!(
-            "mysql://{}:{}@{}:{}/{}",
-            config.username, config.password, config.host, config.port, config.database
-        );
+        // 使用连接选项而不是URL，密码中的特殊字符无需转义
+        let options = MySqlConnectOptions::new()
+            .host(&config.host)
+            .port(config.port)
+            .username(&config.username)
+            .password(&secrets::resolve(&config.password)?)
+            .database(&config.database);
 
-        let pool = MySqlPool::connect(&database_url).await?;
+        let pool = MySqlPool::connect_with(options).await?;
         
         // 创建表
-        let client = Self { pool };
+        let client = Self {
+            pool,
+            batch_size: config.batch_size.clamp(1, MAX_BATCH_ROWS),
+        };
         client.create_tables().await?;
         
         Ok(client)
@@ -32,9 +55,13 @@ impl MariaDbClient {
                 id CHAR(36) PRIMARY KEY,
                 device_id VARCHAR(255) NOT NULL,
                 signal_type VARCHAR(255) NOT NULL,
-                value DOUBLE NOT NULL,
+                value DOUBLE,
+                value_type VARCHAR(16) NOT NULL DEFAULT 'float',
+                value_text TEXT,
                 unit VARCHAR(50),
                 timestamp DATETIME(6) NOT NULL,
+                received_at DATETIME(6),
+                tags JSON,
                 metadata JSON,
                 created_at DATETIME(6) DEFAULT CURRENT_TIMESTAMP(6),
                 INDEX idx_device_id (device_id),
@@ -44,39 +71,143 @@ impl MariaDbClient {
         "#;
 
         sqlx::query(create_table_sql).execute(&self.pool).await?;
+
+        // 旧版本创建的表没有接收时间列
+        sqlx::query("ALTER TABLE device_signals ADD COLUMN IF NOT EXISTS received_at DATETIME(6) AFTER timestamp")
+            .execute(&self.pool)
+            .await?;
+
+        // 旧版本的value列只支持非空数值，字符串和JSON信号存放在value_text中
+        sqlx::query("ALTER TABLE device_signals ADD COLUMN IF NOT EXISTS value_type VARCHAR(16) NOT NULL DEFAULT 'float' AFTER value")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("ALTER TABLE device_signals ADD COLUMN IF NOT EXISTS value_text TEXT AFTER value_type")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("ALTER TABLE device_signals ADD COLUMN IF NOT EXISTS tags JSON AFTER received_at")
+            .execute(&self.pool)
+            .await?;
+
+        let value_nullable: Option<String> = sqlx::query_scalar(
+            "SELECT IS_NULLABLE FROM information_schema.COLUMNS \
+             WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'device_signals' AND COLUMN_NAME = 'value'"
+        )
+            .fetch_optional(&self.pool)
+            .await?;
+        if value_nullable.as_deref() == Some("NO") {
+            sqlx::query("ALTER TABLE device_signals MODIFY COLUMN value DOUBLE NULL")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // HTTP接口的API密钥，保存密钥的SHA-256摘要和加密的签名密钥
+        let create_api_keys_sql = r#"
+            CREATE TABLE IF NOT EXISTS api_keys (
+                id VARCHAR(32) PRIMARY KEY,
+                name VARCHAR(255) NOT NULL,
+                device_id VARCHAR(255),
+                scopes VARCHAR(64) NOT NULL,
+                secret_hash CHAR(64) NOT NULL,
+                signing_secret TEXT,
+                created_at DATETIME(6) NOT NULL,
+                revoked_at DATETIME(6)
+            ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+        "#;
+
+        sqlx::query(create_api_keys_sql).execute(&self.pool).await?;
+        sqlx::query("ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS signing_secret TEXT AFTER secret_hash")
+            .execute(&self.pool)
+            .await?;
+
         log::info!("MariaDB tables created successfully");
         
         Ok(())
     }
 
     pub async fn insert_signal(&self, signal: &DeviceSignal) -> Result<()> {
+        // 多字段信号拆分为多行，需要在同一事务中写入
+        if signal.is_multi_field() {
+            return self.insert_signals(std::slice::from_ref(signal)).await;
+        }
+
         let id = signal.id.unwrap_or_else(|| Uuid::new_v4());
+        let (value, value_type, value_text) = stored_value(signal.value.as_ref());
         
         let insert_sql = r#"
-            INSERT INTO device_signals (id, device_id, signal_type, value, unit, timestamp, metadata)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO device_signals (id, device_id, signal_type, value, value_type, value_text, unit, timestamp, received_at, tags, metadata)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE id = id
         "#;
 
         sqlx::query(insert_sql)
             .bind(id.to_string())
             .bind(&signal.device_id)
             .bind(&signal.signal_type)
-            .bind(signal.value)
+            .bind(value)
+            .bind(value_type)
+            .bind(value_text)
             .bind(&signal.unit)
             .bind(signal.timestamp)
+            .bind(signal.received_at)
+            .bind(stored_tags(signal))
             .bind(signal.metadata.as_ref().map(|m| serde_json::to_string(m).unwrap_or_default()))
             .execute(&self.pool)
-            .await?;
+            .await
+            .map_err(write_error)?;
 
         log::debug!("Inserted signal to MariaDB: {} - {}", signal.device_id, signal.signal_type);
         Ok(())
     }
 
+    /// 批量插入信号
+    ///
+    /// 按`batch_size`分块生成多行`INSERT ... VALUES`，所有分块在同一事务中提交，
+    /// 任一分块失败时整批回滚。多字段信号按字段拆分为多行。
+    pub async fn insert_signals(&self, signals: &[DeviceSignal]) -> Result<()> {
+        if signals.is_empty() {
+            return Ok(());
+        }
+
+        let rows: Vec<DeviceSignal> = signals.iter().flat_map(DeviceSignal::split_fields).collect();
+        let mut tx = self.pool.begin().await?;
+
+        for chunk in rows.chunks(self.batch_size) {
+            let mut builder: QueryBuilder<MySql> = QueryBuilder::new(
+                "INSERT INTO device_signals (id, device_id, signal_type, value, value_type, value_text, unit, timestamp, received_at, tags, metadata) "
+            );
+
+            builder.push_values(chunk, |mut row, signal| {
+                let (value, value_type, value_text) = stored_value(signal.value.as_ref());
+                row.push_bind(signal.id.unwrap_or_else(Uuid::new_v4).to_string())
+                    .push_bind(signal.device_id.clone())
+                    .push_bind(signal.signal_type.clone())
+                    .push_bind(value)
+                    .push_bind(value_type)
+                    .push_bind(value_text)
+                    .push_bind(signal.unit.clone())
+                    .push_bind(signal.timestamp)
+                    .push_bind(signal.received_at)
+                    .push_bind(stored_tags(signal))
+                    .push_bind(signal.metadata.as_ref().map(|m| serde_json::to_string(m).unwrap_or_default()));
+            });
+
+            // 相同ID的信号已写入时保持原记录，重试和缓冲重放不会产生重复行
+            builder.push(" ON DUPLICATE KEY UPDATE id = id");
+            builder.build().execute(&mut *tx).await.map_err(write_error)?;
+        }
+
+        tx.commit().await?;
+
+        log::debug!("Inserted {} signals ({} rows) to MariaDB", signals.len(), rows.len());
+        Ok(())
+    }
+
     pub async fn get_signals_by_device(&self, device_id: &str, limit: Option<i32>) -> Result<Vec<DeviceSignal>> {
         let limit = limit.unwrap_or(100);
         
         let query_sql = r#"
-            SELECT id, device_id, signal_type, value, unit, timestamp, metadata
+            SELECT id, device_id, signal_type, value, value_type, value_text, unit, timestamp, received_at, tags, metadata
             FROM device_signals
             WHERE device_id = ?
             ORDER BY timestamp DESC
@@ -94,14 +225,26 @@ impl MariaDbClient {
             let metadata_str: Option<String> = row.try_get("metadata")?;
             let metadata = metadata_str
                 .and_then(|s| serde_json::from_str(&s).ok());
+            let tags_str: Option<String> = row.try_get("tags")?;
+            let tags = tags_str
+                .and_then(|s| serde_json::from_str(&s).ok())
+                .unwrap_or_default();
 
             let signal = DeviceSignal {
                 id: Some(Uuid::parse_str(&row.try_get::<String, _>("id")?)?),
+                idempotency_key: None,
                 device_id: row.try_get("device_id")?,
                 signal_type: row.try_get("signal_type")?,
-                value: row.try_get("value")?,
+                value: Some(SignalValue::restore(
+                    &row.try_get::<String, _>("value_type")?,
+                    row.try_get("value")?,
+                    row.try_get("value_text")?,
+                )?),
+                fields: Default::default(),
+                tags,
                 unit: row.try_get("unit")?,
                 timestamp: row.try_get::<DateTime<Utc>, _>("timestamp")?,
+                received_at: row.try_get::<Option<DateTime<Utc>>, _>("received_at")?,
                 metadata,
             };
             signals.push(signal);
@@ -114,7 +257,7 @@ impl MariaDbClient {
         let limit = limit.unwrap_or(50);
         
         let query_sql = r#"
-            SELECT id, device_id, signal_type, value, unit, timestamp, metadata
+            SELECT id, device_id, signal_type, value, value_type, value_text, unit, timestamp, received_at, tags, metadata
             FROM device_signals
             ORDER BY timestamp DESC
             LIMIT ?
@@ -130,14 +273,26 @@ impl MariaDbClient {
             let metadata_str: Option<String> = row.try_get("metadata")?;
             let metadata = metadata_str
                 .and_then(|s| serde_json::from_str(&s).ok());
+            let tags_str: Option<String> = row.try_get("tags")?;
+            let tags = tags_str
+                .and_then(|s| serde_json::from_str(&s).ok())
+                .unwrap_or_default();
 
             let signal = DeviceSignal {
                 id: Some(Uuid::parse_str(&row.try_get::<String, _>("id")?)?),
+                idempotency_key: None,
                 device_id: row.try_get("device_id")?,
                 signal_type: row.try_get("signal_type")?,
-                value: row.try_get("value")?,
+                value: Some(SignalValue::restore(
+                    &row.try_get::<String, _>("value_type")?,
+                    row.try_get("value")?,
+                    row.try_get("value_text")?,
+                )?),
+                fields: Default::default(),
+                tags,
                 unit: row.try_get("unit")?,
                 timestamp: row.try_get::<DateTime<Utc>, _>("timestamp")?,
+                received_at: row.try_get::<Option<DateTime<Utc>>, _>("received_at")?,
                 metadata,
             };
             signals.push(signal);
@@ -146,6 +301,188 @@ impl MariaDbClient {
         Ok(signals)
     }
 
+    /// 按时间范围查询信号，聚合时按时间窗口分桶计算
+    ///
+    /// InfluxDB不可用时作为趋势查询的后备数据源。与InfluxDB的`GROUP BY`一致，
+    /// `limit`限制每个设备和信号类型序列的数据点数，而不是结果总数。
+    pub async fn query_signals(&self, query: &SignalQuery) -> Result<Vec<SignalSeries>> {
+        let time_column = if query.aggregation.is_some() { "bucket" } else { "timestamp" };
+        let mut builder: QueryBuilder<MySql> = QueryBuilder::new(format!(
+            "SELECT device_id, signal_type, {0}, value FROM (SELECT t.*, ROW_NUMBER() OVER (PARTITION BY device_id, signal_type ORDER BY {0} ASC) AS series_rn FROM (",
+            time_column
+        ));
+
+        match query.aggregation {
+            None => {
+                builder.push("SELECT device_id, signal_type, timestamp, value FROM device_signals");
+                Self::push_query_filter(&mut builder, query);
+            },
+            Some((Aggregation::Last, window)) => {
+                // 取每个时间桶内最后一条记录
+                let seconds = window.seconds();
+                builder.push("SELECT device_id, signal_type, bucket, value FROM (SELECT device_id, signal_type, value, ");
+                builder.push(format!("CAST(FLOOR(UNIX_TIMESTAMP(timestamp) / {0}) * {0} AS SIGNED) AS bucket, ", seconds));
+                builder.push(format!(
+                    "ROW_NUMBER() OVER (PARTITION BY device_id, signal_type, FLOOR(UNIX_TIMESTAMP(timestamp) / {}) ORDER BY timestamp DESC) AS rn FROM device_signals",
+                    seconds
+                ));
+                Self::push_query_filter(&mut builder, query);
+                builder.push(") last_rows WHERE rn = 1");
+            },
+            Some((aggregation, window)) => {
+                let function = match aggregation {
+                    Aggregation::Min => "MIN",
+                    Aggregation::Max => "MAX",
+                    _ => "AVG",
+                };
+                builder.push(format!(
+                    "SELECT device_id, signal_type, CAST(FLOOR(UNIX_TIMESTAMP(timestamp) / {0}) * {0} AS SIGNED) AS bucket, {1}(value) AS value FROM device_signals",
+                    window.seconds(), function
+                ));
+                Self::push_query_filter(&mut builder, query);
+                builder.push(" GROUP BY device_id, signal_type, bucket");
+            },
+        }
+
+        builder.push(") t) s WHERE series_rn <= ");
+        builder.push_bind(query.limit);
+        builder.push(format!(" ORDER BY {} ASC", time_column));
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        let mut points = Vec::new();
+        for row in rows {
+            let timestamp = match query.aggregation {
+                None => row.try_get::<DateTime<Utc>, _>("timestamp")?,
+                Some(_) => {
+                    let bucket: i64 = row.try_get("bucket")?;
+                    DateTime::<Utc>::from_timestamp(bucket, 0)
+                        .ok_or_else(|| anyhow::anyhow!("Invalid bucket timestamp: {}", bucket))?
+                },
+            };
+            points.push((
+                row.try_get("device_id")?,
+                row.try_get("signal_type")?,
+                SeriesPoint {
+                    timestamp,
+                    value: row.try_get("value")?,
+                },
+            ));
+        }
+        Ok(SignalSeries::group(points))
+    }
+
+    /// 追加时间范围、设备和信号类型过滤条件
+    fn push_query_filter(builder: &mut QueryBuilder<MySql>, query: &SignalQuery) {
+        builder.push(" WHERE timestamp >= ");
+        builder.push_bind(query.from);
+        builder.push(" AND timestamp < ");
+        builder.push_bind(query.to);
+        // 字符串和JSON信号没有数值，不参与趋势查询
+        builder.push(" AND value IS NOT NULL");
+
+        for (column, values) in [("device_id", &query.device_ids), ("signal_type", &query.signal_types)] {
+            if values.is_empty() {
+                continue;
+            }
+            builder.push(format!(" AND {} IN (", column));
+            let mut separated = builder.separated(", ");
+            for value in values {
+                separated.push_bind(value.clone());
+            }
+            separated.push_unseparated(")");
+        }
+    }
+
+    pub async fn insert_api_key(&self, stored: &StoredApiKey) -> Result<()> {
+        let key = &stored.key;
+        let scopes: Vec<&str> = key.scopes.iter().map(Scope::as_str).collect();
+        let signing_secret = stored.signing_secret.as_deref().map(secrets::encrypt).transpose()?;
+
+        sqlx::query(
+            "INSERT INTO api_keys (id, name, device_id, scopes, secret_hash, signing_secret, created_at, revoked_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+            .bind(&key.id)
+            .bind(&key.name)
+            .bind(&key.device_id)
+            .bind(scopes.join(","))
+            .bind(&stored.secret_hash)
+            .bind(signing_secret)
+            .bind(key.created_at)
+            .bind(key.revoked_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_api_key(&self, id: &str) -> Result<Option<StoredApiKey>> {
+        let row = sqlx::query(
+            "SELECT id, name, device_id, scopes, secret_hash, signing_secret, created_at, revoked_at \
+             FROM api_keys WHERE id = ?"
+        )
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        // 无法解密（例如本地密钥文件丢失）时只影响签名认证，令牌认证仍可使用
+        let signing_secret = match row.try_get::<Option<String>, _>("signing_secret")? {
+            Some(encrypted) => match secrets::resolve(&encrypted) {
+                Ok(secret) => Some(secret),
+                Err(e) => {
+                    log::warn!("Failed to decrypt signing secret of API key {}: {}", id, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        Ok(Some(StoredApiKey {
+            key: Self::api_key_from_row(&row)?,
+            secret_hash: row.try_get("secret_hash")?,
+            signing_secret,
+        }))
+    }
+
+    pub async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let rows = sqlx::query(
+            "SELECT id, name, device_id, scopes, created_at, revoked_at FROM api_keys ORDER BY created_at DESC"
+        )
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(Self::api_key_from_row).collect()
+    }
+
+    /// 吊销API密钥，密钥不存在或已吊销时返回false
+    pub async fn revoke_api_key(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("UPDATE api_keys SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    fn api_key_from_row(row: &sqlx::mysql::MySqlRow) -> Result<ApiKey> {
+        let scopes: String = row.try_get("scopes")?;
+
+        Ok(ApiKey {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            device_id: row.try_get("device_id")?,
+            scopes: scopes.split(',').filter_map(Scope::parse).collect(),
+            created_at: row.try_get::<DateTime<Utc>, _>("created_at")?,
+            revoked_at: row.try_get::<Option<DateTime<Utc>>, _>("revoked_at")?,
+        })
+    }
+
     pub async fn health_check(&self) -> Result<bool> {
         let result = sqlx::query("SELECT 1 as test")
             .fetch_one(&self.pool)
@@ -153,4 +490,69 @@ impl MariaDbClient {
             
         Ok(result.try_get::<i32, _>("test")? == 1)
     }
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl SignalSink for MariaDbClient {
+    fn name(&self) -> &'static str {
+        "MariaDB"
+    }
+
+    async fn write_signal(&self, signal: &DeviceSignal) -> Result<()> {
+        self.insert_signal(signal).await
+    }
+
+    async fn write_batch(&self, signals: &[DeviceSignal]) -> Result<()> {
+        self.insert_signals(signals).await
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        MariaDbClient::health_check(self).await
+    }
+
+    /// 批量插入在一个事务中执行
+    fn atomic_batches(&self) -> bool {
+        true
+    }
+}
+
+/// 数据本身无法写入的错误标记为`Rejected`
+///
+/// SQLSTATE 22（数据异常，例如超长、超出范围）和23（约束冲突），以及字符集不支持的
+/// 字符串（1366，SQLSTATE为HY000）。
+fn write_error(error: sqlx::Error) -> anyhow::Error {
+    let rejected = match &error {
+        sqlx::Error::Database(db) => {
+            let state = db.code().map_or(false, |code| code.starts_with("22") || code.starts_with("23"));
+            let number = db.try_downcast_ref::<MySqlDatabaseError>().map(|e| e.number());
+            state || number == Some(1366)
+        },
+        _ => false,
+    };
+
+    if rejected {
+        Rejected(format!("MariaDB rejected signal: {}", error)).into()
+    } else {
+        error.into()
+    }
+}
+
+/// 信号值对应的`value`、`value_type`和`value_text`列
+///
+/// 整数在`value_text`中额外保存原值，以免经DOUBLE列丢失精度。
+fn stored_value(value: Option<&SignalValue>) -> (Option<f64>, &'static str, Option<String>) {
+    match value {
+        Some(SignalValue::Integer(integer)) => (Some(*integer as f64), "integer", Some(integer.to_string())),
+        Some(value) => (value.as_f64(), value.type_name(), value.as_text()),
+        None => (None, "float", None),
+    }
+}
+
+/// `tags`列存放的JSON，没有标签时为NULL
+fn stored_tags(signal: &DeviceSignal) -> Option<String> {
+    if signal.tags.is_empty() {
+        None
+    } else {
+        serde_json::to_string(&signal.tags).ok()
+    }
+}
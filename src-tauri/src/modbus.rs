@@ -0,0 +1,300 @@
+use tokio_modbus::client::{tcp, Context, Reader};
+use tokio_modbus::Slave;
+use anyhow::Result;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::lookup_host;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use crate::models::{DeviceSignal, SignalValue};
+use crate::config::{ModbusConfig, ModbusDataType, ModbusDeviceConfig, ModbusRegisterConfig, ModbusRegisterKind, ModbusWordOrder};
+use crate::services::SharedDataService;
+
+/// 读取失败后重新连接前的等待时间
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+impl ModbusDataType {
+    /// 占用的16位寄存器数量
+    fn register_count(&self) -> u16 {
+        match self {
+            ModbusDataType::Bool | ModbusDataType::U16 | ModbusDataType::I16 => 1,
+            ModbusDataType::U32 | ModbusDataType::I32 | ModbusDataType::F32 => 2,
+        }
+    }
+
+    /// 将寄存器原始值解码为信号值，`bool`解码为布尔值，其余类型解码为数值
+    fn decode(&self, words: &[u16], word_order: ModbusWordOrder) -> Result<SignalValue> {
+        if words.len() < self.register_count() as usize {
+            return Err(anyhow::anyhow!("Expected {} registers, got {}", self.register_count(), words.len()));
+        }
+
+        let dword = || match word_order {
+            ModbusWordOrder::Big => ((words[0] as u32) << 16) | words[1] as u32,
+            ModbusWordOrder::Little => ((words[1] as u32) << 16) | words[0] as u32,
+        };
+
+        Ok(SignalValue::Float(match self {
+            ModbusDataType::Bool => return Ok(SignalValue::Boolean(words[0] != 0)),
+            ModbusDataType::U16 => words[0] as f64,
+            ModbusDataType::I16 => words[0] as i16 as f64,
+            ModbusDataType::U32 => dword() as f64,
+            ModbusDataType::I32 => dword() as i32 as f64,
+            ModbusDataType::F32 => f32::from_bits(dword()) as f64,
+        }))
+    }
+}
+
+/// 单个Modbus TCP设备的轮询器
+struct ModbusPoller {
+    device: ModbusDeviceConfig,
+    context: Option<Context>,
+}
+
+impl ModbusPoller {
+    fn new(device: ModbusDeviceConfig) -> Self {
+        Self {
+            device,
+            context: None,
+        }
+    }
+
+    async fn connect(&mut self) -> Result<&mut Context> {
+        if self.context.is_none() {
+            let address = format!("{}:{}", self.device.host, self.device.port);
+            let socket_addr: SocketAddr = lookup_host(&address)
+                .await?
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Unable to resolve Modbus host {}", address))?;
+
+            let context = tcp::connect_slave(socket_addr, Slave(self.device.unit_id)).await?;
+            log::info!("✅ Modbus connected to {} (unit {})", address, self.device.unit_id);
+            self.context = Some(context);
+        }
+
+        self.context
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("Modbus context unavailable"))
+    }
+
+    /// 读取所有配置的寄存器并转换为设备信号
+    async fn poll(&mut self) -> Result<Vec<DeviceSignal>> {
+        let registers = self.device.registers.clone();
+        let device_id = self.device.device_id.clone();
+        let unit_id = self.device.unit_id;
+        let context = self.connect().await?;
+        let timestamp = Utc::now();
+
+        let mut signals = Vec::with_capacity(registers.len());
+        for register in &registers {
+            // 单个寄存器的异常响应（例如地址不存在）只跳过该寄存器，连接错误才重新连接
+            let raw = match read_register(context, register).await? {
+                Ok(raw) => raw,
+                Err(e) => {
+                    log::warn!("Skipping Modbus register {} on {}: {}", register.address, device_id, e);
+                    continue;
+                }
+            };
+            // 缩放和偏移只作用于数值，布尔值原样保留
+            let value = match raw {
+                SignalValue::Float(raw) => SignalValue::Float(raw * register.scale + register.offset),
+                other => other,
+            };
+            if !value.is_finite() {
+                log::warn!("Skipping non-finite Modbus value from {} register {}", device_id, register.address);
+                continue;
+            }
+
+            signals.push(DeviceSignal {
+                id: Some(Uuid::new_v4()),
+                idempotency_key: None,
+                device_id: device_id.clone(),
+                signal_type: register.signal_type.clone(),
+                value: Some(value),
+                fields: BTreeMap::new(),
+                tags: BTreeMap::new(),
+                unit: register.unit.clone(),
+                timestamp,
+                received_at: Some(timestamp),
+                metadata: Some(serde_json::json!({
+                    "source": "modbus",
+                    "unit_id": unit_id,
+                    "address": register.address,
+                })),
+            });
+        }
+
+        Ok(signals)
+    }
+
+    async fn run(mut self, data_service: SharedDataService) {
+        let mut interval = tokio::time::interval(Duration::from_millis(self.device.poll_interval_ms.max(10)));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            match self.poll().await {
+                Ok(signals) => {
+                    if let Err(e) = data_service.current().process_batch_signals(&signals).await {
+                        log::error!("Failed to process Modbus signals from {}: {}", self.device.device_id, e);
+                    }
+                },
+                Err(e) => {
+                    log::warn!("Modbus poll failed for {}: {} (reconnecting)", self.device.device_id, e);
+                    self.context = None;
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+/// 读取单个寄存器定义对应的原始值
+///
+/// 外层错误表示连接或传输失败，内层错误是设备对该寄存器的异常响应或无法解码的数据。
+async fn read_register(context: &mut Context, register: &ModbusRegisterConfig) -> Result<Result<SignalValue>> {
+    let count = register.data_type.register_count();
+
+    let words = match register.kind {
+        ModbusRegisterKind::Holding => context.read_holding_registers(register.address, count).await?,
+        ModbusRegisterKind::Input => context.read_input_registers(register.address, count).await?,
+        // 线圈和离散输入只有一个位，直接作为布尔值返回
+        ModbusRegisterKind::Coil => {
+            let bits = context.read_coils(register.address, 1).await?;
+            return Ok(bits
+                .map(|bits| SignalValue::Boolean(bits.first().copied().unwrap_or(false)))
+                .map_err(|e| anyhow::anyhow!("Modbus exception: {:?}", e)));
+        },
+        ModbusRegisterKind::Discrete => {
+            let bits = context.read_discrete_inputs(register.address, 1).await?;
+            return Ok(bits
+                .map(|bits| SignalValue::Boolean(bits.first().copied().unwrap_or(false)))
+                .map_err(|e| anyhow::anyhow!("Modbus exception: {:?}", e)));
+        },
+    };
+
+    Ok(match words {
+        Ok(words) => register.data_type.decode(&words, register.word_order),
+        Err(e) => Err(anyhow::anyhow!("Modbus exception: {:?}", e)),
+    })
+}
+
+/// 按配置为每个Modbus设备启动轮询任务
+pub fn spawn(config: &ModbusConfig, data_service: SharedDataService) -> Vec<JoinHandle<()>> {
+    if !config.enabled {
+        return Vec::new();
+    }
+
+    let mut tasks = Vec::with_capacity(config.devices.len());
+    for device in &config.devices {
+        if device.registers.is_empty() {
+            log::warn!("Modbus device {} has no registers configured", device.device_id);
+            continue;
+        }
+
+        log::info!(
+            "Starting Modbus poller for {} at {}:{} ({} registers every {} ms)",
+            device.device_id, device.host, device.port, device.registers.len(), device.poll_interval_ms
+        );
+        tasks.push(tokio::spawn(ModbusPoller::new(device.clone()).run(data_service.clone())));
+    }
+    tasks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future;
+    use tokio::net::TcpListener;
+    use tokio_modbus::server::tcp::{accept_tcp_connection, Server};
+    use tokio_modbus::{ExceptionCode, Request, Response};
+
+    /// 只有地址0到2的保持寄存器，其余地址返回异常
+    struct Simulator;
+
+    impl tokio_modbus::server::Service for Simulator {
+        type Request = Request<'static>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = future::Ready<std::result::Result<Response, ExceptionCode>>;
+
+        fn call(&self, request: Self::Request) -> Self::Future {
+            let registers = [250u16, 0x3FC0, 0x0000];
+            future::ready(match request {
+                Request::ReadHoldingRegisters(address, count) => registers
+                    .get(address as usize..(address + count) as usize)
+                    .map(|words| Response::ReadHoldingRegisters(words.to_vec()))
+                    .ok_or(ExceptionCode::IllegalDataAddress),
+                _ => Err(ExceptionCode::IllegalFunction),
+            })
+        }
+    }
+
+    async fn start_simulator() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = Server::new(listener);
+
+        tokio::spawn(async move {
+            let new_service = |_| Ok(Some(Simulator));
+            let on_connected = |stream, socket_addr| async move {
+                accept_tcp_connection(stream, socket_addr, new_service)
+            };
+            let _ = server.serve(&on_connected, |_: std::io::Error| {}).await;
+        });
+        address
+    }
+
+    fn device(address: SocketAddr) -> ModbusDeviceConfig {
+        toml::from_str(&format!(
+            r#"
+            device_id = "plc-1"
+            host = "{}"
+            port = {}
+
+            [[registers]]
+            address = 0
+            scale = 0.1
+            signal_type = "temperature"
+
+            [[registers]]
+            address = 100
+            signal_type = "missing"
+
+            [[registers]]
+            address = 1
+            data_type = "f32"
+            signal_type = "pressure"
+
+            [[registers]]
+            address = 0
+            data_type = "bool"
+            scale = 10.0
+            signal_type = "running"
+            "#,
+            address.ip(),
+            address.port()
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn poll_skips_registers_with_exceptions() {
+        let address = start_simulator().await;
+        let mut poller = ModbusPoller::new(device(address));
+
+        let signals = poller.poll().await.unwrap();
+
+        let types: Vec<&str> = signals.iter().map(|signal| signal.signal_type.as_str()).collect();
+        assert_eq!(types, ["temperature", "pressure", "running"]);
+        assert_eq!(signals[0].value, Some(SignalValue::Float(25.0)));
+        assert_eq!(signals[1].value, Some(SignalValue::Float(1.5)));
+        assert_eq!(signals[2].value, Some(SignalValue::Boolean(true)));
+        assert!(poller.context.is_some(), "connection should be kept after a register exception");
+
+        // 保持连接，下一次轮询复用同一连接
+        assert_eq!(poller.poll().await.unwrap().len(), 3);
+    }
+}
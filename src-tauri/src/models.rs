@@ -1,35 +1,362 @@
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use chrono::{DateTime, TimeZone, Utc};
 use uuid::Uuid;
+use crate::config::IngestConfig;
+use crate::validation::{validate_request, FieldError, ValidationErrors};
 
 /// 设备信号数据模型
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceSignal {
     pub id: Option<Uuid>,
+    /// 客户端提供的幂等键（请求中的`id`或`Idempotency-Key`请求头），`id`由其派生
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
     pub device_id: String,
     pub signal_type: String,
-    pub value: f64,
+    /// 单值信号的值，多字段信号为None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<SignalValue>,
+    /// 多字段信号的字段，例如三相电表同时上报的电压、电流和功率因数
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, SignalValue>,
+    /// 附加标签
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
     pub unit: Option<String>,
+    /// 信号产生时间，设备未提供时为网关接收时间
     pub timestamp: DateTime<Utc>,
+    /// 网关接收时间
+    #[serde(default)]
+    pub received_at: Option<DateTime<Utc>>,
     pub metadata: Option<serde_json::Value>,
 }
 
+impl DeviceSignal {
+    /// 是否为多字段信号
+    pub fn is_multi_field(&self) -> bool {
+        !self.fields.is_empty()
+    }
+
+    /// 值和所有字段都不是NaN或无穷大
+    pub fn is_finite(&self) -> bool {
+        self.value.iter().chain(self.fields.values()).all(SignalValue::is_finite)
+    }
+
+    /// 拆分为单值信号
+    ///
+    /// 多字段信号的每个字段生成一个`signal_type`为`{signal_type}.{field}`的信号，
+    /// ID由原信号ID和字段名确定性生成；单值信号原样返回。
+    pub fn split_fields(&self) -> Vec<DeviceSignal> {
+        if !self.is_multi_field() {
+            return vec![self.clone()];
+        }
+
+        self.fields
+            .iter()
+            .map(|(field, value)| DeviceSignal {
+                id: self.id.map(|id| Uuid::new_v5(&id, field.as_bytes())),
+                idempotency_key: self.idempotency_key.clone(),
+                device_id: self.device_id.clone(),
+                signal_type: format!("{}.{}", self.signal_type, field),
+                value: Some(value.clone()),
+                fields: BTreeMap::new(),
+                tags: self.tags.clone(),
+                unit: self.unit.clone(),
+                timestamp: self.timestamp,
+                received_at: self.received_at,
+                metadata: self.metadata.clone(),
+            })
+            .collect()
+    }
+}
+
+/// 信号值
+///
+/// JSON中的数字、布尔值、字符串、对象或数组分别对应不同的变体，
+/// 原有只发送数值的客户端不受影响。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "serde_json::Value", into = "serde_json::Value")]
+pub enum SignalValue {
+    Float(f64),
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Json(serde_json::Value),
+}
+
+impl SignalValue {
+    /// 类型名称，用于存储和日志
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            SignalValue::Float(_) => "float",
+            SignalValue::Integer(_) => "integer",
+            SignalValue::Boolean(_) => "boolean",
+            SignalValue::String(_) => "string",
+            SignalValue::Json(_) => "json",
+        }
+    }
+
+    /// 数值形式，布尔值转换为1/0，字符串和JSON没有数值形式
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            SignalValue::Float(value) => Some(*value),
+            SignalValue::Integer(value) => Some(*value as f64),
+            SignalValue::Boolean(value) => Some(if *value { 1.0 } else { 0.0 }),
+            SignalValue::String(_) | SignalValue::Json(_) => None,
+        }
+    }
+
+    /// 浮点值不是NaN或无穷大，JSON无法表示这些值
+    pub fn is_finite(&self) -> bool {
+        match self {
+            SignalValue::Float(value) => value.is_finite(),
+            _ => true,
+        }
+    }
+
+    /// 文本形式，数值类型返回None
+    pub fn as_text(&self) -> Option<String> {
+        match self {
+            SignalValue::String(value) => Some(value.clone()),
+            SignalValue::Json(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    /// 按类型名称从存储的数值和文本还原
+    pub fn restore(type_name: &str, number: Option<f64>, text: Option<String>) -> Result<Self> {
+        let missing = || anyhow::anyhow!("Missing stored value for {} signal", type_name);
+
+        Ok(match type_name {
+            "float" => SignalValue::Float(number.ok_or_else(missing)?),
+            "integer" => match text.and_then(|t| t.parse().ok()) {
+                Some(value) => SignalValue::Integer(value),
+                None => SignalValue::Integer(number.ok_or_else(missing)? as i64),
+            },
+            "boolean" => SignalValue::Boolean(number.ok_or_else(missing)? != 0.0),
+            "string" => SignalValue::String(text.ok_or_else(missing)?),
+            "json" => SignalValue::Json(serde_json::from_str(&text.ok_or_else(missing)?)?),
+            other => return Err(anyhow::anyhow!("Unknown signal value type: {}", other)),
+        })
+    }
+}
+
+impl From<f64> for SignalValue {
+    fn from(value: f64) -> Self {
+        SignalValue::Float(value)
+    }
+}
+
+impl TryFrom<serde_json::Value> for SignalValue {
+    type Error = String;
+
+    fn try_from(value: serde_json::Value) -> std::result::Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::Null => Err("signal value must not be null".to_string()),
+            serde_json::Value::Bool(value) => Ok(SignalValue::Boolean(value)),
+            // 带小数点或超出i64范围的数字按浮点数处理
+            serde_json::Value::Number(number) => match number.as_i64() {
+                Some(value) => Ok(SignalValue::Integer(value)),
+                None => number
+                    .as_f64()
+                    .map(SignalValue::Float)
+                    .ok_or_else(|| format!("unsupported number: {}", number)),
+            },
+            serde_json::Value::String(value) => Ok(SignalValue::String(value)),
+            other => Ok(SignalValue::Json(other)),
+        }
+    }
+}
+
+impl From<SignalValue> for serde_json::Value {
+    fn from(value: SignalValue) -> Self {
+        match value {
+            SignalValue::Float(value) => serde_json::Number::from_f64(value)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            SignalValue::Integer(value) => value.into(),
+            SignalValue::Boolean(value) => value.into(),
+            SignalValue::String(value) => value.into(),
+            SignalValue::Json(value) => value,
+        }
+    }
+}
+
+impl std::fmt::Display for SignalValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignalValue::Float(value) => write!(f, "{}", value),
+            SignalValue::Integer(value) => write!(f, "{}", value),
+            SignalValue::Boolean(value) => write!(f, "{}", value),
+            SignalValue::String(value) => write!(f, "{}", value),
+            SignalValue::Json(value) => write!(f, "{}", value),
+        }
+    }
+}
+
 /// API接收的设备信号请求
 #[derive(Debug, Deserialize)]
 pub struct DeviceSignalRequest {
+    /// 客户端提供的信号ID，重试时使用相同的ID可避免重复写入
+    pub id: Option<String>,
     pub device_id: String,
     pub signal_type: String,
-    pub value: f64,
+    /// 单值信号的值，与`fields`二选一
+    pub value: Option<SignalValue>,
+    /// 多字段信号的字段
+    pub fields: Option<BTreeMap<String, SignalValue>>,
+    /// 附加标签
+    pub tags: Option<BTreeMap<String, String>>,
     pub unit: Option<String>,
+    /// 设备时间戳，RFC3339字符串或Unix纪元数值
+    pub timestamp: Option<DeviceTimestamp>,
+    /// 数值时间戳的单位: s, ms, us, ns；未指定时按数量级推断
+    pub timestamp_unit: Option<String>,
     pub metadata: Option<serde_json::Value>,
 }
 
+/// 设备上报的时间戳
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum DeviceTimestamp {
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl DeviceTimestamp {
+    /// 解析为UTC时间
+    pub fn parse(&self, unit: Option<&str>) -> Result<DateTime<Utc>> {
+        match self {
+            DeviceTimestamp::Integer(value) => Self::from_epoch_integer(*value, unit),
+            DeviceTimestamp::Float(value) => Self::from_epoch(*value, unit),
+            DeviceTimestamp::Text(text) => match (text.parse::<i64>(), text.parse::<f64>()) {
+                (Ok(value), _) => Self::from_epoch_integer(value, unit),
+                (_, Ok(value)) => Self::from_epoch(value, unit),
+                _ => Ok(DateTime::parse_from_rfc3339(text)
+                    .map_err(|e| anyhow::anyhow!("Invalid RFC3339 timestamp '{}': {}", text, e))?
+                    .with_timezone(&Utc)),
+            },
+        }
+    }
+
+    /// 整数时间戳用整数运算换算，纳秒精度的时间戳不会因浮点舍入而偏移
+    fn from_epoch_integer(value: i64, unit: Option<&str>) -> Result<DateTime<Utc>> {
+        if value < 0 {
+            return Err(anyhow::anyhow!("Invalid epoch timestamp: {}", value));
+        }
+
+        let nanos = value
+            .checked_mul(Self::nanos_per_unit(value as f64, unit)?)
+            .ok_or_else(|| anyhow::anyhow!("Epoch timestamp out of range: {}", value))?;
+
+        Ok(Utc.timestamp_nanos(nanos))
+    }
+
+    fn from_epoch(value: f64, unit: Option<&str>) -> Result<DateTime<Utc>> {
+        if !value.is_finite() || value < 0.0 {
+            return Err(anyhow::anyhow!("Invalid epoch timestamp: {}", value));
+        }
+
+        let nanos = value * Self::nanos_per_unit(value, unit)? as f64;
+        if nanos >= i64::MAX as f64 {
+            return Err(anyhow::anyhow!("Epoch timestamp out of range: {}", value));
+        }
+
+        Ok(Utc.timestamp_nanos(nanos as i64))
+    }
+
+    /// 每个时间单位包含的纳秒数
+    ///
+    /// 未指定单位时按数量级推断：秒 < 1e11 <= 毫秒 < 1e14 <= 微秒 < 1e17 <= 纳秒
+    fn nanos_per_unit(magnitude: f64, unit: Option<&str>) -> Result<i64> {
+        let unit = match unit {
+            Some(unit) => unit,
+            None if magnitude < 1e11 => "s",
+            None if magnitude < 1e14 => "ms",
+            None if magnitude < 1e17 => "us",
+            None => "ns",
+        };
+
+        match unit {
+            "s" => Ok(1_000_000_000),
+            "ms" => Ok(1_000_000),
+            "us" | "µs" => Ok(1_000),
+            "ns" => Ok(1),
+            other => Err(anyhow::anyhow!("Unsupported timestamp unit: {}", other)),
+        }
+    }
+}
+
+/// 由客户端幂等键派生信号ID使用的命名空间
+const IDEMPOTENCY_NAMESPACE: Uuid = Uuid::from_u128(0x6f1c_2b8e_4d3a_5e7f_9a10_b2c3_d4e5_f607);
+
+/// 客户端ID本身是UUID时直接使用，否则按设备和幂等键确定性地生成UUID
+fn signal_id_for(device_id: &str, key: &str) -> Uuid {
+    match Uuid::parse_str(key) {
+        Ok(id) => id,
+        Err(_) => Uuid::new_v5(&IDEMPOTENCY_NAMESPACE, format!("{}\n{}", device_id, key).as_bytes()),
+    }
+}
+
+impl DeviceSignalRequest {
+    /// 转换为设备信号
+    ///
+    /// 设备提供时间戳时保留为信号时间，同时记录网关接收时间；
+    /// 校验规则见`validation::validate_request`。
+    pub fn into_signal(self, config: &IngestConfig) -> std::result::Result<DeviceSignal, ValidationErrors> {
+        let received_at = Utc::now();
+        let timestamp = validate_request(&self, config, received_at)?;
+
+        let id = match &self.id {
+            Some(key) => signal_id_for(&self.device_id, key),
+            None => Uuid::new_v4(),
+        };
+
+        Ok(DeviceSignal {
+            id: Some(id),
+            idempotency_key: self.id,
+            device_id: self.device_id,
+            signal_type: self.signal_type,
+            value: self.value,
+            fields: self.fields.unwrap_or_default(),
+            tags: self.tags.unwrap_or_default(),
+            unit: self.unit,
+            timestamp,
+            received_at: Some(received_at),
+            metadata: self.metadata,
+        })
+    }
+}
+
+/// 消息队列中的信号载荷，可以是单个信号或信号数组
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum DeviceSignalPayload {
+    Single(DeviceSignalRequest),
+    Batch(Vec<DeviceSignalRequest>),
+}
+
+impl DeviceSignalPayload {
+    pub fn into_requests(self) -> Vec<DeviceSignalRequest> {
+        match self {
+            DeviceSignalPayload::Single(request) => vec![request],
+            DeviceSignalPayload::Batch(requests) => requests,
+        }
+    }
+}
+
 /// API响应
 #[derive(Debug, Serialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub message: String,
     pub data: Option<T>,
+    /// 字段级错误详情
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
 }
 
 impl<T> ApiResponse<T> {
@@ -38,6 +365,7 @@ impl<T> ApiResponse<T> {
             success: true,
             message: "Success".to_string(),
             data: Some(data),
+            errors: Vec::new(),
         }
     }
 
@@ -46,37 +374,199 @@ impl<T> ApiResponse<T> {
             success: false,
             message,
             data: None,
+            errors: Vec::new(),
+        }
+    }
+
+    /// 部分成功或失败但仍需返回数据的响应
+    pub fn partial(message: String, data: T) -> Self {
+        Self {
+            success: false,
+            message,
+            data: Some(data),
+            errors: Vec::new(),
         }
     }
+
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
 }
 
-/// InfluxDB时间序列数据点
+/// 批量接收中单个信号的处理状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemStatus {
+    /// 已写入所有sink或转存到本地缓冲
+    Accepted,
+    /// 未通过校验，未写入任何sink
+    Rejected,
+    /// 至少一个sink写入失败，或相同ID的信号正在写入
+    Failed,
+}
+
+/// 批量接收中单个信号的处理结果
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchItemResult {
+    /// 信号在请求数组中的位置
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    pub status: ItemStatus,
+    /// 校验错误
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    /// 写入失败的sink及错误信息
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub sink_errors: BTreeMap<String, String>,
+    /// 已转存到本地缓冲、稍后重放的sink
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub buffered: Vec<String>,
+    /// 时间窗口内已接收过相同ID的信号，本次未重复写入
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub duplicate: bool,
+    /// 相同ID的信号正在由其他请求写入，本次未写入，需要稍后重试
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub in_flight: bool,
+}
+
+/// 批量接收结果
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    pub accepted: usize,
+    pub rejected: usize,
+    pub failed: usize,
+    pub results: Vec<BatchItemResult>,
+}
+
+/// 时间范围查询的聚合函数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation {
+    Mean,
+    Min,
+    Max,
+    Last,
+}
+
+impl Aggregation {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "mean" | "avg" => Some(Aggregation::Mean),
+            "min" => Some(Aggregation::Min),
+            "max" => Some(Aggregation::Max),
+            "last" => Some(Aggregation::Last),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Aggregation::Mean => "mean",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+            Aggregation::Last => "last",
+        }
+    }
+}
+
+/// 聚合时间窗口
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregationWindow {
+    OneMinute,
+    FiveMinutes,
+    OneHour,
+}
+
+impl AggregationWindow {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "1m" => Some(AggregationWindow::OneMinute),
+            "5m" => Some(AggregationWindow::FiveMinutes),
+            "1h" => Some(AggregationWindow::OneHour),
+            _ => None,
+        }
+    }
+
+    /// InfluxQL duration写法
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AggregationWindow::OneMinute => "1m",
+            AggregationWindow::FiveMinutes => "5m",
+            AggregationWindow::OneHour => "1h",
+        }
+    }
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            AggregationWindow::OneMinute => 60,
+            AggregationWindow::FiveMinutes => 300,
+            AggregationWindow::OneHour => 3600,
+        }
+    }
+}
+
+/// 信号时间范围查询条件
 #[derive(Debug, Clone)]
-pub struct TimeSeriesPoint {
-    pub measurement: String,
-    pub tags: std::collections::HashMap<String, String>,
-    pub fields: std::collections::HashMap<String, f64>,
+pub struct SignalQuery {
+    pub device_ids: Vec<String>,
+    pub signal_types: Vec<String>,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// 聚合函数和时间窗口，为None时返回原始数据
+    pub aggregation: Option<(Aggregation, AggregationWindow)>,
+    /// 每个设备和信号类型序列最多返回的数据点数
+    pub limit: u32,
+}
+
+/// 查询结果中的单个数据点
+#[derive(Debug, Clone, Serialize)]
+pub struct SeriesPoint {
     pub timestamp: DateTime<Utc>,
+    pub value: f64,
 }
 
-impl From<&DeviceSignal> for TimeSeriesPoint {
-    fn from(signal: &DeviceSignal) -> Self {
-        let mut tags = std::collections::HashMap::new();
-        tags.insert("device_id".to_string(), signal.device_id.clone());
-        tags.insert("signal_type".to_string(), signal.signal_type.clone());
-        
-        if let Some(unit) = &signal.unit {
-            tags.insert("unit".to_string(), unit.clone());
-        }
+/// 按设备和信号类型分组的查询结果
+#[derive(Debug, Clone, Serialize)]
+pub struct SignalSeries {
+    pub device_id: String,
+    pub signal_type: String,
+    pub points: Vec<SeriesPoint>,
+}
 
-        let mut fields = std::collections::HashMap::new();
-        fields.insert("value".to_string(), signal.value);
+impl SignalSeries {
+    /// 将`(device_id, signal_type, point)`按设备和信号类型归并为序列
+    pub fn group<I>(rows: I) -> Vec<SignalSeries>
+    where
+        I: IntoIterator<Item = (String, String, SeriesPoint)>,
+    {
+        let mut series: Vec<SignalSeries> = Vec::new();
 
-        Self {
-            measurement: "device_signals".to_string(),
-            tags,
-            fields,
-            timestamp: signal.timestamp,
+        for (device_id, signal_type, point) in rows {
+            match series.iter_mut().find(|s| s.device_id == device_id && s.signal_type == signal_type) {
+                Some(existing) => existing.points.push(point),
+                None => series.push(SignalSeries {
+                    device_id,
+                    signal_type,
+                    points: vec![point],
+                }),
+            }
+        }
+
+        for s in &mut series {
+            s.points.sort_by_key(|p| p.timestamp);
         }
+
+        series
     }
+}
+
+/// InfluxDB时间序列数据点
+#[derive(Debug, Clone)]
+pub struct TimeSeriesPoint {
+    pub measurement: String,
+    pub tags: std::collections::HashMap<String, String>,
+    pub fields: std::collections::HashMap<String, SignalValue>,
+    pub timestamp: DateTime<Utc>,
 }
\ No newline at end of file
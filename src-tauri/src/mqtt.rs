@@ -0,0 +1,272 @@
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use anyhow::Result;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::models::{DeviceSignal, DeviceSignalRequest};
+use crate::config::{IngestConfig, MqttConfig};
+use crate::secrets;
+use crate::collector;
+use crate::services::SharedDataService;
+
+/// 主题模板，例如`devices/{device_id}/signals/{signal_type}`
+///
+/// 占位符在订阅时替换为`+`，收到消息后从对应的主题层级取值。
+#[derive(Debug, Clone)]
+struct TopicPattern {
+    filter: String,
+    device_id_level: Option<usize>,
+    signal_type_level: Option<usize>,
+}
+
+impl TopicPattern {
+    fn parse(pattern: &str) -> Self {
+        let mut device_id_level = None;
+        let mut signal_type_level = None;
+
+        let levels: Vec<&str> = pattern
+            .split('/')
+            .enumerate()
+            .map(|(index, level)| match level {
+                "{device_id}" => {
+                    device_id_level = Some(index);
+                    "+"
+                },
+                "{signal_type}" => {
+                    signal_type_level = Some(index);
+                    "+"
+                },
+                other => other,
+            })
+            .collect();
+
+        Self {
+            filter: levels.join("/"),
+            device_id_level,
+            signal_type_level,
+        }
+    }
+
+    /// 判断主题是否匹配过滤器（支持`+`和`#`通配符）
+    fn matches(&self, topic: &str) -> bool {
+        let mut topic_levels = topic.split('/');
+
+        for filter_level in self.filter.split('/') {
+            match (filter_level, topic_levels.next()) {
+                ("#", _) => return true,
+                ("+", Some(_)) => {},
+                (expected, Some(actual)) if expected == actual => {},
+                _ => return false,
+            }
+        }
+
+        topic_levels.next().is_none()
+    }
+
+    fn level<'a>(&self, topic: &'a str, level: Option<usize>) -> Option<&'a str> {
+        level.and_then(|index| topic.split('/').nth(index))
+    }
+}
+
+/// 将MQTT消息转换为设备信号
+///
+/// 载荷可以是完整的`DeviceSignalRequest`（或数组）、只包含`value`等字段的对象，
+/// 也可以是单个数值、布尔值或字符串，非JSON的文本载荷按字符串处理；
+/// `device_id`和`signal_type`优先从主题中提取。
+fn decode_message(pattern: &TopicPattern, topic: &str, payload: &[u8], ingest: &IngestConfig) -> Result<Vec<DeviceSignal>> {
+    let device_id = pattern.level(topic, pattern.device_id_level);
+    let signal_type = pattern.level(topic, pattern.signal_type_level);
+
+    let value: serde_json::Value = match serde_json::from_slice(payload) {
+        Ok(value) => value,
+        Err(e) => match std::str::from_utf8(payload) {
+            Ok(text) => serde_json::Value::String(text.trim().to_string()),
+            Err(_) => return Err(e.into()),
+        },
+    };
+    let items = match value {
+        serde_json::Value::Array(items) => items,
+        other => vec![other],
+    };
+
+    let mut signals = Vec::new();
+    for item in items {
+        let mut object = match item {
+            serde_json::Value::Object(object) => object,
+            value @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_) | serde_json::Value::String(_)) => {
+                let mut object = serde_json::Map::new();
+                object.insert("value".to_string(), value);
+                object
+            },
+            other => return Err(anyhow::anyhow!("Unsupported MQTT payload: {}", other)),
+        };
+
+        if let Some(device_id) = device_id {
+            object.insert("device_id".to_string(), device_id.into());
+        }
+        if let Some(signal_type) = signal_type {
+            object.insert("signal_type".to_string(), signal_type.into());
+        }
+
+        let request: DeviceSignalRequest = serde_json::from_value(serde_json::Value::Object(object))?;
+        signals.push(request.into_signal(ingest)?);
+    }
+
+    Ok(signals)
+}
+
+/// 按配置启动MQTT订阅任务
+pub fn spawn(config: &MqttConfig, data_service: SharedDataService) -> Vec<JoinHandle<()>> {
+    if !config.enabled {
+        return Vec::new();
+    }
+
+    if config.topics.is_empty() {
+        log::warn!("MQTT ingestion enabled but no topics configured");
+        return Vec::new();
+    }
+
+    let mut config = config.clone();
+    config.password = match secrets::resolve_optional(config.password.as_deref()) {
+        Ok(password) => password,
+        Err(e) => {
+            log::error!("MQTT ingestion disabled, failed to resolve password: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel(10_000);
+    let ingest = data_service.current().config().ingest.clone();
+    vec![
+        tokio::spawn(run_listener(config, ingest, sender)),
+        tokio::spawn(collector::run_writer("MQTT", receiver, data_service)),
+    ]
+}
+
+/// 维护MQTT连接并把解码后的信号交给写入任务
+///
+/// 断线后由rumqttc事件循环自动重连，每次连接成功都会重新订阅。
+async fn run_listener(config: MqttConfig, ingest: IngestConfig, sender: mpsc::Sender<Vec<DeviceSignal>>) {
+    let patterns: Vec<TopicPattern> = config.topics.iter().map(|t| TopicPattern::parse(t)).collect();
+    let qos = match config.qos {
+        0 => QoS::AtMostOnce,
+        2 => QoS::ExactlyOnce,
+        _ => QoS::AtLeastOnce,
+    };
+
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(config.keep_alive_secs.max(5)));
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        options.set_credentials(username, password);
+    }
+
+    let (client, mut event_loop) = AsyncClient::new(options, 100);
+    log::info!("MQTT ingestion connecting to {}:{}", config.host, config.port);
+
+    loop {
+        match event_loop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                log::info!("✅ MQTT connected to {}:{}", config.host, config.port);
+                for pattern in &patterns {
+                    match client.try_subscribe(pattern.filter.as_str(), qos) {
+                        Ok(_) => log::info!("MQTT subscribed to {}", pattern.filter),
+                        Err(e) => log::error!("Failed to subscribe to {}: {}", pattern.filter, e),
+                    }
+                }
+            },
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let pattern = match patterns.iter().find(|p| p.matches(&publish.topic)) {
+                    Some(pattern) => pattern,
+                    None => continue,
+                };
+
+                match decode_message(pattern, &publish.topic, &publish.payload, &ingest) {
+                    Ok(signals) if !signals.is_empty() => {
+                        if sender.send(signals).await.is_err() {
+                            log::warn!("MQTT writer stopped, shutting down listener");
+                            return;
+                        }
+                    },
+                    Ok(_) => {},
+                    Err(e) => log::warn!("Skipping MQTT message on {}: {}", publish.topic, e),
+                }
+            },
+            Ok(_) => {},
+            Err(e) => {
+                log::warn!("MQTT connection error: {} (reconnecting)", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::SignalValue;
+
+    fn ingest() -> IngestConfig {
+        IngestConfig {
+            max_clock_skew_secs: 300,
+            max_signal_age_secs: 0,
+            max_metadata_bytes: 4096,
+            max_fields: 64,
+            max_batch_size: 1000,
+            max_body_bytes: 1024 * 1024,
+            dedup_window_secs: 0,
+            dedup_capacity: 0,
+        }
+    }
+
+    #[test]
+    fn topic_pattern_replaces_placeholders_with_wildcards() {
+        let pattern = TopicPattern::parse("devices/{device_id}/signals/{signal_type}");
+
+        assert_eq!(pattern.filter, "devices/+/signals/+");
+        assert!(pattern.matches("devices/pump-1/signals/temperature"));
+        assert!(!pattern.matches("devices/pump-1/signals"));
+        assert!(!pattern.matches("devices/pump-1/signals/temperature/raw"));
+        assert!(!pattern.matches("sensors/pump-1/signals/temperature"));
+    }
+
+    #[test]
+    fn topic_pattern_supports_multi_level_wildcard() {
+        let pattern = TopicPattern::parse("plant/{device_id}/#");
+
+        assert!(pattern.matches("plant/line-2/temperature"));
+        assert!(pattern.matches("plant/line-2/a/b/c"));
+        assert!(!pattern.matches("factory/line-2/temperature"));
+    }
+
+    #[test]
+    fn decode_message_takes_ids_from_topic() {
+        let pattern = TopicPattern::parse("devices/{device_id}/signals/{signal_type}");
+        let signals = decode_message(&pattern, "devices/pump-1/signals/temperature", b"23.5", &ingest()).unwrap();
+
+        assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].device_id, "pump-1");
+        assert_eq!(signals[0].signal_type, "temperature");
+        assert_eq!(signals[0].value, Some(SignalValue::Float(23.5)));
+    }
+
+    #[test]
+    fn decode_message_accepts_objects_arrays_and_text() {
+        let pattern = TopicPattern::parse("devices/{device_id}/signals/{signal_type}");
+        let topic = "devices/pump-1/signals/state";
+
+        let signals = decode_message(&pattern, topic, br#"{"value": 1.5, "unit": "bar"}"#, &ingest()).unwrap();
+        assert_eq!(signals[0].unit.as_deref(), Some("bar"));
+
+        let signals = decode_message(&pattern, topic, br#"[{"value": 1}, {"value": 2}]"#, &ingest()).unwrap();
+        assert_eq!(signals.len(), 2);
+
+        let signals = decode_message(&pattern, topic, b"running", &ingest()).unwrap();
+        assert_eq!(signals[0].value, Some(SignalValue::String("running".to_string())));
+    }
+
+    #[test]
+    fn decode_message_rejects_payload_without_device_id() {
+        let pattern = TopicPattern::parse("signals/{signal_type}");
+        assert!(decode_message(&pattern, "signals/temperature", b"23.5", &ingest()).is_err());
+    }
+}
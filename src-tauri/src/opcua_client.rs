@@ -0,0 +1,331 @@
+use opcua::client::prelude::*;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use crate::models::{DeviceSignal, SignalValue};
+use crate::config::{OpcUaConfig, OpcUaNodeConfig};
+use crate::secrets;
+use crate::collector;
+use crate::services::SharedDataService;
+
+/// 会话断开后重新连接前的等待时间
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+/// 会话循环处理消息的间隔（毫秒）
+const SESSION_POLL_INTERVAL_MS: u64 = 10;
+/// 等待写入的通知数量上限，写入任务长时间重试时超出的通知转存到本地缓冲
+const CHANNEL_CAPACITY: usize = 10_000;
+
+/// 写入任务来不及处理、转存到本地缓冲的信号数量
+static SPILLED_SIGNALS: AtomicU64 = AtomicU64::new(0);
+/// 写入任务来不及处理、且无法转存到本地缓冲而丢弃的信号数量
+static DROPPED_SIGNALS: AtomicU64 = AtomicU64::new(0);
+
+/// 启动以来转存到本地缓冲的信号数量
+pub fn spilled_signals() -> u64 {
+    SPILLED_SIGNALS.load(Ordering::Relaxed)
+}
+
+/// 启动以来丢弃的信号数量
+pub fn dropped_signals() -> u64 {
+    DROPPED_SIGNALS.load(Ordering::Relaxed)
+}
+
+/// 写入任务来不及处理时把信号直接转存到各sink的本地缓冲，随缓冲一起重放
+fn spill(runtime: &Handle, data_service: &SharedDataService, signals: Vec<DeviceSignal>) {
+    let data_service = data_service.clone();
+    runtime.spawn(async move {
+        let count = signals.len() as u64;
+        match data_service.current().spill(&signals).await {
+            Ok(_) => {
+                SPILLED_SIGNALS.fetch_add(count, Ordering::Relaxed);
+                log::warn!("OPC UA writer is falling behind, buffered {} signals locally", count);
+            },
+            Err(e) => {
+                DROPPED_SIGNALS.fetch_add(count, Ordering::Relaxed);
+                log::error!("OPC UA writer is falling behind, dropped {} signals: {}", count, e);
+            },
+        }
+    });
+}
+
+/// 监控项对应的信号属性
+#[derive(Debug, Clone)]
+struct MonitoredSignal {
+    device_id: String,
+    signal_type: String,
+    unit: Option<String>,
+}
+
+/// 将数据变化通知转换为设备信号
+fn to_signal(signal: &MonitoredSignal, node_id: &NodeId, data_value: &DataValue) -> Option<DeviceSignal> {
+    let value = match data_value.value.as_ref()? {
+        Variant::Boolean(value) => SignalValue::Boolean(*value),
+        Variant::String(value) => SignalValue::String(value.as_ref().to_string()),
+        Variant::LocalizedText(text) => SignalValue::String(text.text.as_ref().to_string()),
+        other => SignalValue::Float(other.as_f64()?),
+    };
+    if !value.is_finite() {
+        log::warn!("Skipping non-finite OPC UA value from {}", node_id);
+        return None;
+    }
+
+    let status = data_value.status.unwrap_or(StatusCode::Good);
+    let received_at = chrono::Utc::now();
+    // 优先使用服务器报告的源时间戳作为信号时间
+    let source_timestamp = data_value.source_timestamp.map(|t| t.as_chrono());
+    let timestamp = source_timestamp.unwrap_or(received_at);
+
+    Some(DeviceSignal {
+        id: Some(Uuid::new_v4()),
+        idempotency_key: None,
+        device_id: signal.device_id.clone(),
+        signal_type: signal.signal_type.clone(),
+        value: Some(value),
+        fields: BTreeMap::new(),
+        tags: BTreeMap::new(),
+        unit: signal.unit.clone(),
+        timestamp,
+        received_at: Some(received_at),
+        metadata: Some(serde_json::json!({
+            "source": "opcua",
+            "node_id": node_id.to_string(),
+            "status_code": status.name(),
+            "source_timestamp": source_timestamp.map(|t| t.to_rfc3339()),
+        })),
+    })
+}
+
+/// 读取节点的浏览名作为默认信号类型
+fn read_browse_names(session: &Session, nodes: &[OpcUaNodeConfig]) -> Result<Vec<Option<String>>> {
+    let mut read_ids = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        read_ids.push(ReadValueId {
+            node_id: NodeId::from_str(&node.node_id)
+                .map_err(|_| anyhow::anyhow!("Invalid OPC UA node id: {}", node.node_id))?,
+            attribute_id: AttributeId::BrowseName as u32,
+            index_range: UAString::null(),
+            data_encoding: QualifiedName::null(),
+        });
+    }
+
+    let values = session
+        .read(&read_ids, TimestampsToReturn::Neither, 0.0)
+        .map_err(|status| anyhow::anyhow!("Failed to read browse names: {}", status))?;
+
+    Ok(values
+        .into_iter()
+        .map(|value| match value.value {
+            Some(Variant::QualifiedName(name)) => Some(name.name.to_string()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// 解析配置的安全策略和消息安全模式
+///
+/// 用户名认证要求启用签名或加密，否则密码会以明文发送。
+fn security(config: &OpcUaConfig) -> Result<(SecurityPolicy, MessageSecurityMode)> {
+    let policy = match config.security_policy.trim() {
+        "None" => SecurityPolicy::None,
+        "Basic128Rsa15" => SecurityPolicy::Basic128Rsa15,
+        "Basic256" => SecurityPolicy::Basic256,
+        "Basic256Sha256" => SecurityPolicy::Basic256Sha256,
+        "Aes128Sha256RsaOaep" => SecurityPolicy::Aes128Sha256RsaOaep,
+        "Aes256Sha256RsaPss" => SecurityPolicy::Aes256Sha256RsaPss,
+        other => return Err(anyhow::anyhow!("Unsupported OPC UA security policy: {}", other)),
+    };
+    let mode = match config.security_mode.trim() {
+        "None" => MessageSecurityMode::None,
+        "Sign" => MessageSecurityMode::Sign,
+        "SignAndEncrypt" => MessageSecurityMode::SignAndEncrypt,
+        other => return Err(anyhow::anyhow!("Unsupported OPC UA security mode: {}", other)),
+    };
+
+    if (policy == SecurityPolicy::None) != (mode == MessageSecurityMode::None) {
+        return Err(anyhow::anyhow!(
+            "OPC UA security policy {} cannot be used with security mode {}",
+            config.security_policy, config.security_mode
+        ));
+    }
+    if config.username.is_some() && mode == MessageSecurityMode::None {
+        return Err(anyhow::anyhow!(
+            "OPC UA username authentication requires security_mode Sign or SignAndEncrypt"
+        ));
+    }
+
+    Ok((policy, mode))
+}
+
+/// 连接服务器、创建订阅并阻塞运行会话，直到连接断开或写入任务停止
+fn run_session(
+    config: &OpcUaConfig,
+    sender: &mpsc::Sender<Vec<DeviceSignal>>,
+    data_service: &SharedDataService,
+    runtime: &Handle,
+) -> Result<()> {
+    let (policy, mode) = security(config)?;
+
+    let mut client = ClientBuilder::new()
+        .application_name("Tauri Device Gateway")
+        .application_uri("urn:tauri-device-gateway")
+        .product_uri("urn:tauri-device-gateway")
+        .pki_dir(config.pki_dir())
+        .trust_server_certs(config.trust_server_certs)
+        .create_sample_keypair(true)
+        .session_retry_limit(3)
+        .client()
+        .ok_or_else(|| anyhow::anyhow!("Invalid OPC UA client configuration"))?;
+
+    let password = secrets::resolve_optional(config.password.as_deref())?;
+    let (identity, token_policy) = match (&config.username, password) {
+        (Some(username), Some(password)) => (
+            IdentityToken::UserName(username.clone(), password),
+            UserTokenPolicy {
+                policy_id: UAString::from("username"),
+                token_type: UserTokenType::UserName,
+                issued_token_type: UAString::null(),
+                issuer_endpoint_url: UAString::null(),
+                security_policy_uri: UAString::null(),
+            },
+        ),
+        (Some(_), None) => return Err(anyhow::anyhow!("OPC UA password is required when username is set")),
+        _ => (IdentityToken::Anonymous, UserTokenPolicy::anonymous()),
+    };
+
+    // 按URL、安全策略和模式匹配服务器端点，用户身份在激活会话时提交
+    let endpoint: EndpointDescription = (
+        config.endpoint_url.as_str(),
+        policy.to_str(),
+        mode,
+        token_policy,
+    ).into();
+
+    let session = client
+        .connect_to_endpoint(endpoint, identity)
+        .map_err(|status| anyhow::anyhow!("Failed to connect to {}: {}", config.endpoint_url, status))?;
+
+    {
+        let session = session.read();
+        let browse_names = read_browse_names(&session, &config.nodes)?;
+
+        let mut monitored: HashMap<NodeId, MonitoredSignal> = HashMap::new();
+        let mut items = Vec::with_capacity(config.nodes.len());
+        for (node, browse_name) in config.nodes.iter().zip(browse_names) {
+            let node_id = NodeId::from_str(&node.node_id)
+                .map_err(|_| anyhow::anyhow!("Invalid OPC UA node id: {}", node.node_id))?;
+
+            let signal_type = node.signal_type.clone()
+                .or(browse_name)
+                .unwrap_or_else(|| node.node_id.clone());
+
+            monitored.insert(node_id.clone(), MonitoredSignal {
+                device_id: node.device_id.clone().unwrap_or_else(|| config.device_id.clone()),
+                signal_type,
+                unit: node.unit.clone(),
+            });
+            items.push(MonitoredItemCreateRequest::from(node_id));
+        }
+
+        let sender = sender.clone();
+        let data_service = data_service.clone();
+        let callback_runtime = runtime.clone();
+        let subscription_id = session
+            .create_subscription(
+                config.publishing_interval_ms as f64,
+                10,
+                30,
+                0,
+                0,
+                true,
+                DataChangeCallback::new(move |changed_items| {
+                    let signals: Vec<DeviceSignal> = changed_items
+                        .iter()
+                        .filter_map(|item| {
+                            let node_id = &item.item_to_monitor().node_id;
+                            monitored
+                                .get(node_id)
+                                .and_then(|signal| to_signal(signal, node_id, item.last_value()))
+                        })
+                        .collect();
+
+                    // 回调在会话线程中执行，不能阻塞等待写入任务
+                    if !signals.is_empty() {
+                        if let Err(mpsc::error::TrySendError::Full(signals)) = sender.try_send(signals) {
+                            spill(&callback_runtime, &data_service, signals);
+                        }
+                    }
+                }),
+            )
+            .map_err(|status| anyhow::anyhow!("Failed to create subscription: {}", status))?;
+
+        session
+            .create_monitored_items(subscription_id, TimestampsToReturn::Both, &items)
+            .map_err(|status| anyhow::anyhow!("Failed to create monitored items: {}", status))?;
+
+        log::info!("✅ OPC UA subscribed to {} nodes on {}", items.len(), config.endpoint_url);
+    }
+
+    // 写入任务停止（例如重新加载配置）时结束会话
+    let (stop, stop_receiver) = oneshot::channel();
+    let closed = sender.clone();
+    let watcher = runtime.spawn(async move {
+        closed.closed().await;
+        let _ = stop.send(SessionCommand::Stop);
+    });
+
+    // 会话断开（重试次数耗尽）或收到停止命令后返回
+    Session::run_loop(session, SESSION_POLL_INTERVAL_MS, stop_receiver);
+    watcher.abort();
+
+    if sender.is_closed() {
+        log::info!("OPC UA session to {} stopped", config.endpoint_url);
+        return Ok(());
+    }
+    Err(anyhow::anyhow!("OPC UA session to {} ended", config.endpoint_url))
+}
+
+/// 按配置启动OPC UA订阅任务
+///
+/// 返回写入任务，终止后会话线程随之结束。
+pub fn spawn(config: &OpcUaConfig, data_service: SharedDataService) -> Vec<JoinHandle<()>> {
+    if !config.enabled {
+        return Vec::new();
+    }
+
+    if config.nodes.is_empty() {
+        log::warn!("OPC UA ingestion enabled but no nodes configured");
+        return Vec::new();
+    }
+
+    if let Err(e) = security(config) {
+        log::error!("OPC UA ingestion disabled: {}", e);
+        return Vec::new();
+    }
+    if config.trust_server_certs {
+        log::warn!("⚠️  OPC UA client trusts any server certificate; use only for testing");
+    }
+
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    let config = config.clone();
+    let runtime = Handle::current();
+    let session_data_service = data_service.clone();
+
+    // OPC UA客户端的会话循环是阻塞的，放在独立线程中运行
+    std::thread::spawn(move || loop {
+        if let Err(e) = run_session(&config, &sender, &session_data_service, &runtime) {
+            log::warn!("OPC UA client error: {} (reconnecting in {:?})", e, RECONNECT_DELAY);
+        }
+        if sender.is_closed() {
+            return;
+        }
+        std::thread::sleep(RECONNECT_DELAY);
+    });
+
+    vec![tokio::spawn(collector::run_writer("OPC UA", receiver, data_service))]
+}
@@ -0,0 +1,225 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use crate::config::AppConfig;
+
+/// 返回给前端的敏感配置占位符，保存时替换回原值
+pub const REDACTED: &str = "********";
+
+const ENV_PREFIX: &str = "env:";
+const FILE_PREFIX: &str = "file:";
+const ENCRYPTED_PREFIX: &str = "enc:";
+const NONCE_LEN: usize = 12;
+
+/// 解析敏感配置值
+///
+/// - `env:NAME`：读取环境变量
+/// - `file:/path`：读取文件内容，忽略末尾换行
+/// - `enc:...`：用本地密钥文件解密
+/// - 其他值按明文使用
+pub fn resolve(value: &str) -> Result<String> {
+    if let Some(name) = value.strip_prefix(ENV_PREFIX) {
+        return std::env::var(name).with_context(|| format!("Environment variable {} is not set", name));
+    }
+
+    if let Some(path) = value.strip_prefix(FILE_PREFIX) {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read secret file {}", path))?;
+        return Ok(content.trim_end_matches(['\r', '\n']).to_string());
+    }
+
+    if let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) {
+        return decrypt(encoded);
+    }
+
+    Ok(value.to_string())
+}
+
+pub fn resolve_optional(value: Option<&str>) -> Result<Option<String>> {
+    value.map(resolve).transpose()
+}
+
+/// 是否为环境变量或文件引用，引用本身不是敏感信息
+pub fn is_reference(value: &str) -> bool {
+    value.starts_with(ENV_PREFIX) || value.starts_with(FILE_PREFIX)
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// 用本地密钥加密，返回`enc:`开头的配置值
+///
+/// 密钥文件不存在时自动创建。
+pub fn encrypt(plaintext: &str) -> Result<String> {
+    let cipher = ChaCha20Poly1305::new(&load_or_create_key()?);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt secret"))?;
+
+    let mut payload = nonce.to_vec();
+    payload.extend_from_slice(&ciphertext);
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(payload)))
+}
+
+fn decrypt(encoded: &str) -> Result<String> {
+    let payload = STANDARD.decode(encoded.trim()).context("Encrypted secret is not valid base64")?;
+    if payload.len() <= NONCE_LEN {
+        return Err(anyhow::anyhow!("Encrypted secret is too short"));
+    }
+
+    let key = load_key()?;
+    let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+    let plaintext = ChaCha20Poly1305::new(&key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt secret, check the key file {}", key_path().display()))?;
+
+    String::from_utf8(plaintext).context("Decrypted secret is not valid UTF-8")
+}
+
+/// 本地密钥文件，可通过`GATEWAY_SECRET_KEY_FILE`指定，默认位于配置目录
+pub fn key_path() -> PathBuf {
+    match std::env::var_os("GATEWAY_SECRET_KEY_FILE") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let mut path = AppConfig::get_config_path();
+            path.pop();
+            path.push("secret.key");
+            path
+        }
+    }
+}
+
+fn load_key() -> Result<Key> {
+    let path = key_path();
+    let encoded = fs::read_to_string(&path).with_context(|| format!("Failed to read key file {}", path.display()))?;
+    let bytes = STANDARD.decode(encoded.trim()).context("Key file is not valid base64")?;
+    if bytes.len() != 32 {
+        return Err(anyhow::anyhow!("Key file {} must contain a 32-byte key", path.display()));
+    }
+
+    Ok(*Key::from_slice(&bytes))
+}
+
+fn load_or_create_key() -> Result<Key> {
+    let path = key_path();
+    if path.exists() {
+        return load_key();
+    }
+
+    let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    // 密钥文件只允许当前用户读取
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&path).with_context(|| format!("Failed to create key file {}", path.display()))?;
+    file.write_all(STANDARD.encode(key).as_bytes())?;
+    file.flush()?;
+
+    log::info!("Created secret key file: {}", path.display());
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+
+    /// 所有测试共用一个临时密钥文件，环境变量是进程级的，只设置一次
+    ///
+    /// 初始化时先创建密钥文件，避免并行的测试同时创建。
+    fn use_temp_key() -> &'static tempfile::TempDir {
+        static DIR: OnceLock<tempfile::TempDir> = OnceLock::new();
+        DIR.get_or_init(|| {
+            let dir = tempfile::tempdir().unwrap();
+            std::env::set_var("GATEWAY_SECRET_KEY_FILE", dir.path().join("keys").join("secret.key"));
+            load_or_create_key().unwrap();
+            dir
+        })
+    }
+
+    #[test]
+    fn encrypted_values_round_trip() {
+        use_temp_key();
+
+        let encrypted = encrypt("p@ss=wörd").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("p@ss"));
+        assert_eq!(resolve(&encrypted).unwrap(), "p@ss=wörd");
+
+        // 每次加密使用新的随机数
+        assert_ne!(encrypt("p@ss=wörd").unwrap(), encrypted);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use_temp_key();
+
+        let mode = fs::metadata(key_path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn tampered_values_fail_to_decrypt() {
+        use_temp_key();
+
+        let encrypted = encrypt("secret").unwrap();
+        let mut payload = STANDARD.decode(encrypted.strip_prefix(ENCRYPTED_PREFIX).unwrap()).unwrap();
+        *payload.last_mut().unwrap() ^= 1;
+
+        assert!(resolve(&format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(payload))).is_err());
+        assert!(resolve("enc:not base64!").is_err());
+        assert!(resolve("enc:AAAA").is_err());
+    }
+
+    #[test]
+    fn resolves_environment_references() {
+        let name = format!("GATEWAY_TEST_SECRET_{}", uuid::Uuid::new_v4().simple());
+        std::env::set_var(&name, "from-env");
+
+        let reference = format!("env:{}", name);
+        assert!(is_reference(&reference));
+        assert_eq!(resolve(&reference).unwrap(), "from-env");
+
+        std::env::remove_var(&name);
+        assert!(resolve(&reference).is_err());
+    }
+
+    #[test]
+    fn resolves_file_references() {
+        let dir = use_temp_key();
+        let path = dir.path().join("password.txt");
+        fs::write(&path, "from-file\r\n").unwrap();
+
+        let reference = format!("file:{}", path.display());
+        assert!(is_reference(&reference));
+        assert_eq!(resolve(&reference).unwrap(), "from-file");
+        assert!(resolve(&format!("file:{}", dir.path().join("missing").display())).is_err());
+    }
+
+    #[test]
+    fn plain_values_are_used_as_is() {
+        assert!(!is_reference("plain"));
+        assert!(!is_encrypted("plain"));
+        assert_eq!(resolve("plain").unwrap(), "plain");
+        assert_eq!(resolve_optional(None).unwrap(), None);
+    }
+}
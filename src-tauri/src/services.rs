@@ -1,229 +1,695 @@
 use anyhow::Result;
-use crate::models::DeviceSignal;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use futures::future::join_all;
+use crate::models::{DeviceSignal, SignalQuery, SignalSeries};
 use crate::mariadb::MariaDbClient;
 use crate::influxdb::InfluxDbClient;
 use crate::kafka::KafkaProducer;
 use crate::config::AppConfig;
+use crate::connection::{ConnectionStatus, ManagedClient};
+use crate::buffer::SignalBuffer;
+use crate::dedup::{DedupCache, InFlight, Reservation};
+use crate::auth::{ApiKey, Authenticator, CreatedApiKey, Scope, StoredApiKey};
+use crate::sink::{self, create_sinks, SignalSink};
 
 #[derive(Clone)]
 pub struct DataService {
-    mariadb: MariaDbClient,
-    influxdb: InfluxDbClient,
-    kafka: KafkaProducer,
+    config: AppConfig,
+    sinks: Vec<SinkEntry>,
+    // 查询和测试消息等功能需要具体的客户端，对应sink未启用时为None
+    mariadb: Option<ManagedClient<MariaDbClient>>,
+    influxdb: Option<ManagedClient<InfluxDbClient>>,
+    kafka: Option<ManagedClient<KafkaProducer>>,
+    dedup: Arc<DedupCache>,
+    auth: Arc<Authenticator>,
+    _replay_task: Option<Arc<TaskGuard>>,
+}
+
+/// 已启用的sink及其本地存储转发队列
+#[derive(Clone)]
+struct SinkEntry {
+    sink: Arc<dyn SignalSink>,
+    buffer: Option<Arc<SignalBuffer>>,
+}
+
+impl SinkEntry {
+    /// 状态接口中使用的sink标识
+    fn key(&self) -> String {
+        self.sink.name().to_lowercase()
+    }
+}
+
+/// 信号在单个sink上的投递结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delivery {
+    Written,
+    /// 已转存到本地缓冲，稍后重放
+    Buffered,
+}
+
+/// 单个信号在各sink上的写入结果
+#[derive(Debug, Clone, Default)]
+pub struct WriteOutcome {
+    /// 已转存到本地缓冲、稍后重放的sink
+    pub buffered: Vec<String>,
+    /// 写入失败的sink及错误信息
+    pub failed: BTreeMap<String, String>,
+    /// 时间窗口内已成功写入过相同ID，未重复写入
+    pub duplicate: bool,
+    /// 相同ID的信号正在写入，本次未写入，客户端需要稍后重试
+    pub in_flight: bool,
+}
+
+/// 服务释放时终止对应的后台任务
+struct TaskGuard(tokio::task::JoinHandle<()>);
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// 可热替换的数据服务句柄
+///
+/// Tauri状态和HTTP路由共享同一个句柄。调用方通过`current()`取得当前服务的快照，
+/// 处理中的请求会一直持有旧实例直到完成，因此替换服务时不会丢弃进行中的信号。
+#[derive(Clone)]
+pub struct SharedDataService {
+    inner: Arc<RwLock<Arc<DataService>>>,
+}
+
+impl SharedDataService {
+    pub fn new(service: DataService) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(Arc::new(service))),
+        }
+    }
+
+    /// 获取当前数据服务
+    pub fn current(&self) -> Arc<DataService> {
+        match self.inner.read() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// 替换数据服务，返回被替换的旧实例
+    pub fn replace(&self, service: DataService) -> Arc<DataService> {
+        let mut guard = match self.inner.write() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        std::mem::replace(&mut *guard, Arc::new(service))
+    }
+
+    /// 使用新配置重建数据服务并替换当前实例
+    ///
+    /// 配置校验通过后才会替换，配置有误时继续使用旧服务。
+    /// 旧服务的Kafka缓冲消息会在后台刷新。
+    pub async fn reload(&self, config: &AppConfig) -> Result<()> {
+        log::info!("Reloading data service with new configuration...");
+
+        sink::validate(config)?;
+        let service = DataService::new(config).await;
+        let old_service = self.replace(service);
+
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = old_service.flush(Duration::from_secs(10)) {
+                log::warn!("Failed to flush previous Kafka producer: {}", e);
+            }
+        });
+
+        log::info!("Data service reloaded");
+        Ok(())
+    }
 }
 
 impl DataService {
-    pub async fn new(config: &AppConfig) -> Result<Self> {
+    /// 初始化数据服务
+    ///
+    /// 按`sinks.enabled`创建sink。任一外部服务不可达或配置有误时不会失败：对应客户端
+    /// 标记为`Disconnected`，不可达的服务在后台重连，GUI和HTTP API可以继续使用其余部分。
+    pub async fn new(config: &AppConfig) -> Self {
         log::info!("Initializing data service...");
 
-        // 初始化MariaDB连接
-        let mariadb = match MariaDbClient::new(&config.mariadb).await {
-            Ok(client) => {
-                log::info!("MariaDB client initialized");
-                client
-            },
-            Err(e) => {
-                log::error!("Failed to initialize MariaDB: {}", e);
-                return Err(e);
-            }
+        let sinks = create_sinks(config).await;
+
+        // 打开本地缓冲队列
+        let buffer_dir = config.buffer_dir();
+        if config.buffer.enabled {
+            log::info!("Local signal buffer directory: {}", buffer_dir.display());
+        }
+
+        let mut entries = Vec::new();
+        for sink in sinks.all {
+            let buffer = if config.buffer.enabled {
+                match SignalBuffer::open(&buffer_dir, sink.name(), &config.buffer) {
+                    Ok(buffer) => Some(buffer),
+                    Err(e) => {
+                        log::error!("Failed to open local buffer for {}: {}", sink.name(), e);
+                        log::warn!("Continuing without store-and-forward buffering for {}", sink.name());
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            entries.push(SinkEntry { sink, buffer });
+        }
+
+        if config.auth.enabled && !entries.iter().any(|entry| entry.key() == "mariadb") {
+            log::warn!("⚠️  API authentication is enabled but the MariaDB sink is not; authenticated requests will fail with 503");
+        }
+
+        let replay_task = if entries.iter().any(|entry| entry.buffer.is_some()) {
+            let handle = tokio::spawn(Self::replay_buffers(
+                entries.clone(),
+                Duration::from_secs(config.buffer.replay_interval_secs.max(1)),
+                config.buffer.replay_batch_size,
+            ));
+            Some(Arc::new(TaskGuard(handle)))
+        } else {
+            None
         };
 
-        // 初始化InfluxDB连接
-        let influxdb = match InfluxDbClient::new(&config.influxdb).await {
-            Ok(client) => {
-                log::info!("InfluxDB client initialized");
-                client
-            },
-            Err(e) => {
-                log::error!("Failed to initialize InfluxDB: {}", e);
-                return Err(e);
+        Self {
+            config: config.clone(),
+            sinks: entries,
+            mariadb: sinks.mariadb,
+            influxdb: sinks.influxdb,
+            kafka: sinks.kafka,
+            dedup: DedupCache::shared(&config.ingest),
+            auth: Arc::new(Authenticator::new(&config.auth)),
+            _replay_task: replay_task,
+        }
+    }
+
+    /// 定期检查sink健康状态，恢复后按顺序重放缓冲的信号
+    async fn replay_buffers(entries: Vec<SinkEntry>, interval: Duration, batch_size: usize) {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            for entry in &entries {
+                let buffer = match &entry.buffer {
+                    Some(buffer) => buffer,
+                    None => continue,
+                };
+
+                if buffer.is_empty().await || !matches!(entry.sink.health_check().await, Ok(true)) {
+                    continue;
+                }
+
+                let result = buffer.replay(batch_size, |signals| {
+                    let sink = entry.sink.clone();
+                    async move { sink.write_batch(&signals).await }
+                }).await;
+
+                if let Err(e) = result {
+                    log::warn!("{} buffer replay interrupted: {}", entry.sink.name(), e);
+                }
             }
+        }
+    }
+
+    /// 写入sink，失败时转存到本地缓冲队列
+    ///
+    /// 队列中仍有未投递的数据时直接追加到队尾，避免新数据越过旧数据。
+    async fn write_or_buffer<Fut>(buffer: Option<&SignalBuffer>, signals: &[DeviceSignal], write: Fut) -> Result<Delivery>
+    where
+        Fut: Future<Output = Result<()>>,
+    {
+        let buffer = match buffer {
+            Some(buffer) => buffer,
+            None => return write.await.map(|_| Delivery::Written),
         };
 
-        // 初始化Kafka生产者 - 允许失败
-        let kafka = match KafkaProducer::new(&config.kafka).await {
-            Ok(producer) => {
-                log::info!("Kafka producer initialized");
-                producer
-            },
+        if !buffer.is_empty().await {
+            return buffer.push(signals).await.map(|_| Delivery::Buffered);
+        }
+
+        match write.await {
+            Ok(_) => Ok(Delivery::Written),
+            // 被拒绝的数据重放也不会成功，直接返回给调用方
+            Err(e) if sink::is_rejected(&e) => Err(e),
             Err(e) => {
-                log::warn!("Failed to initialize Kafka producer: {}", e);
-                log::warn!("Continuing without Kafka functionality");
-                return Err(e);  // 暂时仍然返回错误，但消息更友好
+                log::warn!("{} write failed, buffering {} signals locally: {}", buffer.sink(), signals.len(), e);
+                buffer.push(signals).await
+                    .map(|_| Delivery::Buffered)
+                    .map_err(|buffer_error| anyhow::anyhow!("{}; local buffering failed: {}", e, buffer_error))
             }
-        };
-
-        Ok(Self {
-            mariadb,
-            influxdb,
-            kafka,
-        })
+        }
     }
 
-    /// 处理单个设备信号
-    pub async fn process_signal(&self, signal: &DeviceSignal) -> Result<()> {
-        log::debug!("Processing signal: {} - {}", signal.device_id, signal.signal_type);
+    /// 批量写入单个sink，返回每个信号的结果
+    ///
+    /// 批量写入具有原子性的sink整批失败时逐条重试，以确定具体失败的信号：整批被拒绝时
+    /// 无论是否有本地缓冲都逐条重试，可重试的失败再转存到缓冲；没有缓冲时其他失败也逐条重试。
+    /// 其他sink可能已写入部分信号，逐条重试会产生重复数据，整批标记为失败。
+    async fn write_batch_detailed(entry: &SinkEntry, signals: &[DeviceSignal]) -> Vec<Result<Delivery, String>> {
+        let buffer = entry.buffer.as_deref();
+        let error = match Self::write_or_buffer(buffer, signals, entry.sink.write_batch(signals)).await {
+            Ok(delivery) => return vec![Ok(delivery); signals.len()],
+            Err(e) => e,
+        };
 
-        // 并行执行所有操作
-        let maria_future = self.mariadb.insert_signal(signal);
-        let influx_future = self.influxdb.write_signal(signal);
-        let kafka_future = self.kafka.send_signal(signal);
+        // 有缓冲时只有被拒绝的错误会到达这里，其他错误说明转存缓冲也失败了
+        let rejected = sink::is_rejected(&error);
+        if signals.len() == 1
+            || !entry.sink.atomic_batches()
+            || (!rejected && (buffer.is_some() || entry.sink.connection_status() == ConnectionStatus::Disconnected))
+        {
+            return vec![Err(error.to_string()); signals.len()];
+        }
 
-        // 等待所有操作完成
-        let (maria_result, influx_result, kafka_result) = 
-            tokio::join!(maria_future, influx_future, kafka_future);
+        log::warn!("{} batch write failed, retrying {} signals individually: {}", entry.sink.name(), signals.len(), error);
 
-        // 处理结果
+        let mut results = Vec::with_capacity(signals.len());
+        for signal in signals {
+            let write = entry.sink.write_signal(signal);
+            results.push(Self::write_or_buffer(buffer, std::slice::from_ref(signal), write).await.map_err(|e| e.to_string()));
+        }
+        results
+    }
+
+    /// 不尝试写入，直接把信号转存到所有sink的本地缓冲
+    ///
+    /// 采集来源来不及写入时使用，信号随缓冲中的数据一起按顺序重放。
+    /// 任一sink没有本地缓冲或转存失败时返回错误，该sink收不到这些信号。
+    pub async fn spill(&self, signals: &[DeviceSignal]) -> Result<()> {
         let mut errors = Vec::new();
+        for entry in &self.sinks {
+            let result = match &entry.buffer {
+                Some(buffer) => buffer.push(signals).await,
+                None => Err(anyhow::anyhow!("local buffering is disabled")),
+            };
+            if let Err(e) = result {
+                errors.push(format!("{}: {}", entry.sink.name(), e));
+            }
+        }
 
-        if let Err(e) = maria_result {
-            errors.push(format!("MariaDB error: {}", e));
+        if !errors.is_empty() {
+            return Err(anyhow::anyhow!("Failed to buffer signals for {}", errors.join(", ")));
         }
+        Ok(())
+    }
 
-        if let Err(e) = influx_result {
-            errors.push(format!("InfluxDB error: {}", e));
+    /// 各sink缓冲队列中待投递的信号数量
+    pub async fn buffered_counts(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for entry in &self.sinks {
+            if let Some(buffer) = &entry.buffer {
+                counts.insert(entry.key(), buffer.len().await);
+            }
         }
+        counts
+    }
+
+    /// 各sink的连接状态
+    pub fn connection_status(&self) -> HashMap<String, ConnectionStatus> {
+        self.sinks
+            .iter()
+            .map(|entry| (entry.key(), entry.sink.connection_status()))
+            .collect()
+    }
+
+    /// 当前服务使用的配置
+    pub fn config(&self) -> &AppConfig {
+        &self.config
+    }
+
+    /// HTTP接口认证
+    pub fn authenticator(&self) -> &Authenticator {
+        &self.auth
+    }
+
+    /// 刷新Kafka生产者中尚未发送的消息
+    pub fn flush(&self, timeout: Duration) -> Result<()> {
+        match self.kafka.as_ref().map(|kafka| kafka.get()) {
+            Some(Ok(kafka)) => kafka.flush(timeout),
+            _ => Ok(()),
+        }
+    }
+
+    /// 处理单个设备信号
+    pub async fn process_signal(&self, signal: &DeviceSignal) -> Result<()> {
+        log::debug!("Processing signal: {} - {}", signal.device_id, signal.signal_type);
 
-        if let Err(e) = kafka_result {
-            errors.push(format!("Kafka error: {}", e));
+        match self.reserve(signal) {
+            Reservation::Reserved => {},
+            Reservation::Duplicate => {
+                log::debug!("Ignoring duplicate signal {:?} from {}", signal.id, signal.device_id);
+                return Ok(());
+            },
+            Reservation::InFlight => {
+                return Err(InFlight(signal.id.unwrap_or_default()).into());
+            },
         }
 
+        let signals = std::slice::from_ref(signal);
+
+        // 并行写入所有sink，失败的sink转存到本地缓冲
+        let results = join_all(self.sinks.iter().map(|entry| {
+            Self::write_or_buffer(entry.buffer.as_deref(), signals, entry.sink.write_signal(signal))
+        })).await;
+
+        // 处理结果
+        let errors: Vec<String> = self.sinks
+            .iter()
+            .zip(results)
+            .filter_map(|(entry, result)| result.err().map(|e| format!("{} error: {}", entry.sink.name(), e)))
+            .collect();
+
         if !errors.is_empty() {
             log::error!("Signal processing errors: {:?}", errors);
+            self.release(signal);
             return Err(anyhow::anyhow!("Processing errors: {}", errors.join(", ")));
         }
 
+        self.commit(signal);
         log::debug!("Signal processed successfully");
         Ok(())
     }
 
     /// 批量处理设备信号
     pub async fn process_batch_signals(&self, signals: &[DeviceSignal]) -> Result<()> {
-        if signals.is_empty() {
-            return Ok(());
+        let (signals, in_flight) = self.reserve_batch(signals);
+
+        if !signals.is_empty() {
+            let failed = self.write_batch_to(&signals, None).await;
+            let written = failed.is_empty();
+            self.settle_batch(&signals, written);
+
+            if !written {
+                let errors: Vec<String> = failed.iter().map(|(sink, e)| format!("{} error: {}", sink, e)).collect();
+                return Err(anyhow::anyhow!("Batch processing errors: {}", errors.join(", ")));
+            }
+        }
+
+        if !in_flight.is_empty() {
+            return Err(anyhow::anyhow!("{} signals are still being processed by another request", in_flight.len()));
         }
+        Ok(())
+    }
 
-        log::debug!("Processing batch of {} signals", signals.len());
+    /// 过滤时间窗口内已写入的信号，返回需要写入的信号和相同ID正在写入的信号
+    ///
+    /// 返回的待写入信号已标记为正在写入，调用方写入后必须调用`settle_batch`。
+    /// 正在写入的信号结果未知，调用方需要稍后重新检查，不能当作重复丢弃。
+    pub fn reserve_batch<'a>(&self, signals: &'a [DeviceSignal]) -> (Cow<'a, [DeviceSignal]>, Vec<DeviceSignal>) {
+        if !signals.iter().any(|signal| signal.idempotency_key.is_some()) {
+            return (Cow::Borrowed(signals), Vec::new());
+        }
 
-        // 并行执行所有批量操作
-        let mut maria_futures = Vec::new();
+        let mut fresh = Vec::with_capacity(signals.len());
+        let mut in_flight = Vec::new();
+        let mut duplicates = 0;
         for signal in signals {
-            maria_futures.push(self.mariadb.insert_signal(signal));
+            match self.reserve(signal) {
+                Reservation::Reserved => fresh.push(signal.clone()),
+                Reservation::Duplicate => duplicates += 1,
+                Reservation::InFlight => in_flight.push(signal.clone()),
+            }
         }
 
-        let influx_future = self.influxdb.write_batch_signals(signals);
-        let kafka_future = self.kafka.send_batch_signals(signals);
+        if duplicates > 0 {
+            log::debug!("Ignoring {} duplicate signals", duplicates);
+        }
+        (Cow::Owned(fresh), in_flight)
+    }
 
-        // 等待所有操作完成
-        let influx_result = influx_future.await;
-        let kafka_result = kafka_future.await;
+    /// 结束`reserve_batch`返回信号的写入：成功后记录去重，失败时撤销记录允许重试
+    pub fn settle_batch(&self, signals: &[DeviceSignal], written: bool) {
+        for signal in signals {
+            if written {
+                self.commit(signal);
+            } else {
+                self.release(signal);
+            }
+        }
+    }
+
+    /// 批量写入sink，返回写入失败的sink及错误信息
+    ///
+    /// `sinks`为None时写入所有sink，否则只写入列出的sink，调用方可以只重试失败的sink，
+    /// 避免已写入的sink收到重复数据。不做去重检查。
+    pub async fn write_batch_to(&self, signals: &[DeviceSignal], sinks: Option<&BTreeSet<String>>) -> BTreeMap<String, anyhow::Error> {
+        let entries: Vec<&SinkEntry> = self.sinks
+            .iter()
+            .filter(|entry| sinks.map_or(true, |sinks| sinks.contains(&entry.key())))
+            .collect();
+
+        log::debug!("Processing batch of {} signals for {} sinks", signals.len(), entries.len());
+
+        // 并行执行所有批量操作，失败的sink转存到本地缓冲
+        let results = join_all(entries.iter().map(|entry| {
+            Self::write_or_buffer(entry.buffer.as_deref(), signals, entry.sink.write_batch(signals))
+        })).await;
+
+        let failed: BTreeMap<String, anyhow::Error> = entries
+            .iter()
+            .zip(results)
+            .filter_map(|(entry, result)| result.err().map(|e| (entry.key(), e)))
+            .collect();
+
+        if failed.is_empty() {
+            log::debug!("Batch signals processed successfully");
+        }
+        for (sink, e) in &failed {
+            log::error!("Batch processing error on {}: {}", sink, e);
+        }
+        failed
+    }
 
-        // 处理MariaDB结果
-        let mut maria_errors = 0;
-        for future in maria_futures {
-            if let Err(e) = future.await {
-                maria_errors += 1;
-                log::error!("MariaDB batch insert error: {}", e);
+    /// 批量处理设备信号并返回每个信号的写入结果
+    pub async fn process_batch_detailed(&self, signals: &[DeviceSignal]) -> Vec<WriteOutcome> {
+        let mut outcomes = vec![WriteOutcome::default(); signals.len()];
+        if signals.is_empty() {
+            return outcomes;
+        }
+
+        log::debug!("Processing batch of {} signals with per-signal results", signals.len());
+
+        // 时间窗口内已写入或正在写入的信号直接标记，不再写入
+        let mut fresh = Vec::with_capacity(signals.len());
+        let mut fresh_indices = Vec::with_capacity(signals.len());
+        for (index, (outcome, signal)) in outcomes.iter_mut().zip(signals).enumerate() {
+            match self.reserve(signal) {
+                Reservation::Reserved => {
+                    fresh.push(signal.clone());
+                    fresh_indices.push(index);
+                },
+                Reservation::Duplicate => outcome.duplicate = true,
+                Reservation::InFlight => outcome.in_flight = true,
             }
         }
 
-        // 收集错误
-        let mut errors = Vec::new();
+        if !fresh.is_empty() {
+            let results = join_all(self.sinks.iter().map(|entry| Self::write_batch_detailed(entry, &fresh))).await;
+
+            for (entry, sink_results) in self.sinks.iter().zip(results) {
+                for (&index, result) in fresh_indices.iter().zip(sink_results) {
+                    let outcome = &mut outcomes[index];
+                    match result {
+                        Ok(Delivery::Written) => {},
+                        Ok(Delivery::Buffered) => outcome.buffered.push(entry.key()),
+                        Err(e) => {
+                            outcome.failed.insert(entry.key(), e);
+                        },
+                    }
+                }
+            }
 
-        if maria_errors > 0 {
-            errors.push(format!("MariaDB errors: {}/{} failed", maria_errors, signals.len()));
+            // 写入成功的信号记录去重，失败的撤销记录允许客户端重试
+            for (&index, signal) in fresh_indices.iter().zip(&fresh) {
+                if outcomes[index].failed.is_empty() {
+                    self.commit(signal);
+                } else {
+                    self.release(signal);
+                }
+            }
         }
 
-        if let Err(e) = influx_result {
-            errors.push(format!("InfluxDB error: {}", e));
+        let failed = outcomes.iter().filter(|o| !o.failed.is_empty() || o.in_flight).count();
+        if failed > 0 {
+            log::error!("Batch processing: {} of {} signals failed", failed, signals.len());
         }
 
-        if let Err(e) = kafka_result {
-            errors.push(format!("Kafka error: {}", e));
+        outcomes
+    }
+
+    /// 检查带幂等键的信号，未接收过时标记为正在写入
+    fn reserve(&self, signal: &DeviceSignal) -> Reservation {
+        match (&signal.idempotency_key, signal.id) {
+            (Some(_), Some(id)) => self.dedup.reserve(id),
+            _ => Reservation::Reserved,
         }
+    }
 
-        if !errors.is_empty() {
-            log::error!("Batch processing errors: {:?}", errors);
-            return Err(anyhow::anyhow!("Batch processing errors: {}", errors.join(", ")));
+    /// 写入成功后记录去重
+    fn commit(&self, signal: &DeviceSignal) {
+        if let (Some(_), Some(id)) = (&signal.idempotency_key, signal.id) {
+            self.dedup.commit(id);
         }
+    }
 
-        log::debug!("Batch signals processed successfully");
-        Ok(())
+    /// 撤销去重记录
+    fn release(&self, signal: &DeviceSignal) {
+        if let (Some(_), Some(id)) = (&signal.idempotency_key, signal.id) {
+            self.dedup.release(id);
+        }
+    }
+
+    fn mariadb(&self) -> Result<Arc<MariaDbClient>> {
+        match &self.mariadb {
+            Some(mariadb) => mariadb.get(),
+            None => Err(anyhow::anyhow!("MariaDB sink is not enabled")),
+        }
     }
 
     /// 获取设备信号历史
     pub async fn get_device_signals(&self, device_id: &str, limit: Option<i32>) -> Result<Vec<DeviceSignal>> {
-        self.mariadb.get_signals_by_device(device_id, limit).await
+        self.mariadb()?.get_signals_by_device(device_id, limit).await
     }
 
     /// 获取最新信号
     pub async fn get_latest_signals(&self, limit: Option<i32>) -> Result<Vec<DeviceSignal>> {
-        self.mariadb.get_latest_signals(limit).await
+        self.mariadb()?.get_latest_signals(limit).await
+    }
+
+    /// 查询API密钥，结果按`auth.cache_ttl_secs`缓存
+    ///
+    /// MariaDB不可用时使用最近一次查询到的密钥，从未查询过的密钥返回错误。
+    pub async fn api_key(&self, id: &str) -> Result<Option<StoredApiKey>> {
+        if let Some(cached) = self.auth.cached(id) {
+            return Ok(Some(cached));
+        }
+
+        let result = match self.mariadb() {
+            Ok(mariadb) => mariadb.get_api_key(id).await,
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(key) => {
+                if let Some(key) = &key {
+                    self.auth.remember(key);
+                }
+                Ok(key)
+            },
+            Err(e) => match self.auth.last_known(id) {
+                Some(key) => {
+                    log::warn!("API key store unavailable, using last known key {}: {}", id, e);
+                    Ok(Some(key))
+                },
+                None => Err(e),
+            },
+        }
+    }
+
+    /// 创建API密钥，返回的令牌只在此时可见
+    pub async fn create_api_key(&self, name: &str, device_id: Option<String>, scopes: Vec<Scope>) -> Result<CreatedApiKey> {
+        if name.trim().is_empty() {
+            return Err(anyhow::anyhow!("API key name must not be empty"));
+        }
+        if scopes.is_empty() {
+            return Err(anyhow::anyhow!("At least one scope is required"));
+        }
+        let device_id = device_id.filter(|device_id| !device_id.trim().is_empty());
+
+        let (stored, token) = StoredApiKey::generate(name.trim().to_string(), device_id, scopes);
+        self.mariadb()?.insert_api_key(&stored).await?;
+        self.auth.invalidate(&stored.key.id);
+
+        log::info!("Created API key {} ({})", stored.key.id, stored.key.name);
+        Ok(CreatedApiKey { key: stored.key, token })
     }
 
-    /// MariaDB健康检查
-    pub async fn mariadb_health_check(&self) -> Result<bool> {
-        self.mariadb.health_check().await
+    pub async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        self.mariadb()?.list_api_keys().await
     }
 
-    /// InfluxDB健康检查
-    pub async fn influxdb_health_check(&self) -> Result<bool> {
-        self.influxdb.health_check().await
+    /// 吊销API密钥，立即对当前服务生效
+    pub async fn revoke_api_key(&self, id: &str) -> Result<bool> {
+        let revoked = self.mariadb()?.revoke_api_key(id).await?;
+        self.auth.invalidate(id);
+
+        if revoked {
+            log::info!("Revoked API key {}", id);
+        }
+        Ok(revoked)
     }
 
-    /// Kafka健康检查
-    pub async fn kafka_health_check(&self) -> Result<bool> {
-        self.kafka.health_check().await
+    /// 从InfluxDB查询最近的设备信号
+    pub async fn query_recent_signals(&self, device_id: Option<&str>, limit: Option<u32>) -> Result<Vec<DeviceSignal>> {
+        match &self.influxdb {
+            Some(influxdb) => influxdb.get()?.query_recent_signals(device_id, limit).await,
+            None => Err(anyhow::anyhow!("InfluxDB sink is not enabled")),
+        }
+    }
+
+    /// 时间范围和聚合查询
+    ///
+    /// 优先使用InfluxDB，未启用或未连接时回退到MariaDB。`source`可指定数据源。
+    pub async fn query_signals(&self, query: &SignalQuery, source: Option<&str>) -> Result<Vec<SignalSeries>> {
+        let influxdb = self.influxdb.as_ref().and_then(|influxdb| influxdb.get().ok());
+
+        match (source, influxdb) {
+            (Some("influxdb"), Some(influxdb)) | (None, Some(influxdb)) => influxdb.query_signals(query).await,
+            (Some("influxdb"), None) => Err(anyhow::anyhow!("InfluxDB is not enabled or not connected")),
+            (Some("mariadb"), _) | (None, None) => self.mariadb()?.query_signals(query).await,
+            (Some(other), _) => Err(anyhow::anyhow!("Unknown query source: {}", other)),
+        }
+    }
+
+    /// 指定sink的健康检查
+    pub async fn health_check(&self, name: &str) -> Result<bool> {
+        match self.sinks.iter().find(|entry| entry.key() == name.to_lowercase()) {
+            Some(entry) => entry.sink.health_check().await,
+            None => Err(anyhow::anyhow!("Unknown or disabled sink: {}", name)),
+        }
+    }
+
+    /// 所有sink的健康状态
+    pub async fn health_status(&self) -> HashMap<String, bool> {
+        let results = join_all(self.sinks.iter().map(|entry| entry.sink.health_check())).await;
+
+        self.sinks
+            .iter()
+            .zip(results)
+            .map(|(entry, result)| (entry.key(), matches!(result, Ok(true))))
+            .collect()
     }
 
     /// 发送测试消息到Kafka
     pub async fn send_test_message(&self, key: &str, message: &str) -> Result<()> {
-        self.kafka.send_custom_message(key, message).await
+        match &self.kafka {
+            Some(kafka) => kafka.get()?.send_custom_message(key, message).await,
+            None => Err(anyhow::anyhow!("Kafka sink is not enabled")),
+        }
     }
 
     /// 完整的健康检查
     pub async fn full_health_check(&self) -> Result<()> {
         log::info!("Performing full health check...");
 
-        let maria_check = self.mariadb_health_check().await;
-        let influx_check = self.influxdb_health_check().await;
-        let kafka_check = self.kafka_health_check().await;
-
         let mut errors = Vec::new();
 
-        match maria_check {
-            Ok(true) => log::info!("✓ MariaDB connection healthy"),
-            Ok(false) => {
-                errors.push("MariaDB connection unhealthy".to_string());
-                log::error!("✗ MariaDB connection unhealthy");
-            },
-            Err(e) => {
-                errors.push(format!("MariaDB error: {}", e));
-                log::error!("✗ MariaDB error: {}", e);
-            }
-        }
-
-        match influx_check {
-            Ok(true) => log::info!("✓ InfluxDB connection healthy"),
-            Ok(false) => {
-                errors.push("InfluxDB connection unhealthy".to_string());
-                log::error!("✗ InfluxDB connection unhealthy");
-            },
-            Err(e) => {
-                errors.push(format!("InfluxDB error: {}", e));
-                log::error!("✗ InfluxDB error: {}", e);
-            }
-        }
-
-        match kafka_check {
-            Ok(true) => log::info!("✓ Kafka connection healthy"),
-            Ok(false) => {
-                errors.push("Kafka connection unhealthy".to_string());
-                log::error!("✗ Kafka connection unhealthy");
-            },
-            Err(e) => {
-                errors.push(format!("Kafka error: {}", e));
-                log::error!("✗ Kafka error: {}", e);
+        for entry in &self.sinks {
+            let name = entry.sink.name();
+            match entry.sink.health_check().await {
+                Ok(true) => log::info!("✓ {} connection healthy", name),
+                Ok(false) => {
+                    errors.push(format!("{} connection unhealthy", name));
+                    log::error!("✗ {} connection unhealthy", name);
+                },
+                Err(e) => {
+                    errors.push(format!("{} error: {}", name, e));
+                    log::error!("✗ {} error: {}", name, e);
+                }
             }
         }
 
@@ -234,4 +700,4 @@ impl DataService {
         log::info!("✓ All systems healthy");
         Ok(())
     }
-}
\ No newline at end of file
+}
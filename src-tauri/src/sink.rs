@@ -0,0 +1,197 @@
+use async_trait::async_trait;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::sync::Arc;
+use crate::models::DeviceSignal;
+use crate::config::AppConfig;
+use crate::connection::{ConnectionStatus, ManagedClient};
+use crate::mariadb::MariaDbClient;
+use crate::influxdb::InfluxDbClient;
+use crate::kafka::KafkaProducer;
+
+/// 设备信号写入目标
+///
+/// 新的存储或消息系统只需实现此trait并在`create_sinks`中注册，
+/// 即可通过`config.toml`中的`sinks.enabled`启用。
+#[async_trait]
+pub trait SignalSink: Send + Sync {
+    /// sink名称，用于日志、状态和缓冲文件
+    fn name(&self) -> &'static str;
+
+    /// 写入单个信号
+    async fn write_signal(&self, signal: &DeviceSignal) -> Result<()>;
+
+    /// 批量写入信号
+    async fn write_batch(&self, signals: &[DeviceSignal]) -> Result<()>;
+
+    /// 健康检查
+    async fn health_check(&self) -> Result<bool>;
+
+    /// 连接状态
+    fn connection_status(&self) -> ConnectionStatus {
+        ConnectionStatus::Connected
+    }
+
+    /// 批量写入是否在一个事务中完成，失败时没有任何信号被写入
+    ///
+    /// 只有这类sink在批量失败后才能逐条重试，否则已写入的部分会重复写入。
+    fn atomic_batches(&self) -> bool {
+        false
+    }
+}
+
+#[async_trait]
+impl<T: SignalSink + 'static> SignalSink for ManagedClient<T> {
+    fn name(&self) -> &'static str {
+        ManagedClient::name(self)
+    }
+
+    async fn write_signal(&self, signal: &DeviceSignal) -> Result<()> {
+        self.get()?.write_signal(signal).await
+    }
+
+    async fn write_batch(&self, signals: &[DeviceSignal]) -> Result<()> {
+        self.get()?.write_batch(signals).await
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        self.get()?.health_check().await
+    }
+
+    fn connection_status(&self) -> ConnectionStatus {
+        self.status()
+    }
+
+    fn atomic_batches(&self) -> bool {
+        self.get().map_or(false, |client| client.atomic_batches())
+    }
+}
+
+/// sink拒绝写入的数据错误，例如字段类型冲突或超长的值
+///
+/// 重试同样的数据不会成功，缓冲重放时这类信号会被移入死信文件，不再阻塞队列。
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct Rejected(pub String);
+
+/// 错误是否为数据被拒绝（而不是连接或服务暂时不可用）
+pub fn is_rejected(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Rejected>().is_some()
+}
+
+/// 根据配置创建的sink集合
+///
+/// `all`按配置顺序包含全部启用的sink；具名字段保留给查询、测试消息等
+/// 需要具体客户端的功能，未启用时为`None`。
+pub struct Sinks {
+    pub all: Vec<Arc<dyn SignalSink>>,
+    pub mariadb: Option<ManagedClient<MariaDbClient>>,
+    pub influxdb: Option<ManagedClient<InfluxDbClient>>,
+    pub kafka: Option<ManagedClient<KafkaProducer>>,
+}
+
+/// 检查已启用sink的配置，不连接外部服务
+///
+/// 重新加载服务前调用，配置有误时保留当前服务并把全部错误返回给调用方。
+pub fn validate(config: &AppConfig) -> Result<()> {
+    let errors: Vec<String> = config.sinks.enabled
+        .iter()
+        .filter_map(|name| validate_sink(&name.to_lowercase(), config).err())
+        .map(|e| format!("{:#}", e))
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Invalid configuration: {}", errors.join("; ")))
+    }
+}
+
+fn validate_sink(name: &str, config: &AppConfig) -> Result<()> {
+    match name {
+        "mariadb" => MariaDbClient::validate_config(&config.mariadb),
+        "influxdb" => InfluxDbClient::validate_config(&config.influxdb),
+        "kafka" => KafkaProducer::validate_config(&config.kafka),
+        other => Err(anyhow::anyhow!("Unknown sink in sinks.enabled: {}", other)),
+    }
+}
+
+/// 按`sinks.enabled`创建并连接sink
+///
+/// 配置有误的sink只记录一次错误并标记为`Disconnected`，不会在后台反复重连；
+/// 未知的sink名称被忽略。
+pub async fn create_sinks(config: &AppConfig) -> Sinks {
+    let mut sinks = Sinks {
+        all: Vec::new(),
+        mariadb: None,
+        influxdb: None,
+        kafka: None,
+    };
+
+    let mut seen = HashSet::new();
+
+    for name in &config.sinks.enabled {
+        let name = name.to_lowercase();
+        if !seen.insert(name.clone()) {
+            log::warn!("Sink '{}' listed more than once in sinks.enabled, ignoring duplicate", name);
+            continue;
+        }
+
+        let invalid = match validate_sink(&name, config) {
+            Ok(_) => None,
+            Err(e) => Some(e),
+        };
+
+        match name.as_str() {
+            "mariadb" => {
+                let client = match invalid {
+                    Some(e) => ManagedClient::invalid("MariaDB", e),
+                    None => {
+                        let mariadb_config = config.mariadb.clone();
+                        ManagedClient::connect("MariaDB", move || {
+                            let config = mariadb_config.clone();
+                            async move { MariaDbClient::new(&config).await }
+                        }).await
+                    }
+                };
+                sinks.all.push(Arc::new(client.clone()));
+                sinks.mariadb = Some(client);
+            },
+            "influxdb" => {
+                let client = match invalid {
+                    Some(e) => ManagedClient::invalid("InfluxDB", e),
+                    None => {
+                        let influxdb_config = config.influxdb.clone();
+                        ManagedClient::connect("InfluxDB", move || {
+                            let config = influxdb_config.clone();
+                            async move { InfluxDbClient::new(&config).await }
+                        }).await
+                    }
+                };
+                sinks.all.push(Arc::new(client.clone()));
+                sinks.influxdb = Some(client);
+            },
+            "kafka" => {
+                let client = match invalid {
+                    Some(e) => ManagedClient::invalid("Kafka", e),
+                    None => {
+                        let kafka_config = config.kafka.clone();
+                        ManagedClient::connect("Kafka", move || {
+                            let config = kafka_config.clone();
+                            async move { KafkaProducer::new(&config).await }
+                        }).await
+                    }
+                };
+                sinks.all.push(Arc::new(client.clone()));
+                sinks.kafka = Some(client);
+            },
+            other => log::error!("❌ Unknown sink in sinks.enabled, ignoring: {}", other),
+        }
+    }
+
+    if sinks.all.is_empty() {
+        log::warn!("No sinks enabled, received signals will not be stored");
+    }
+
+    sinks
+}
@@ -0,0 +1,111 @@
+use std::fs;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use anyhow::{Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::crypto::ring;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::RootCertStore;
+use crate::config::ServerConfig;
+
+/// 按`[server]`中的证书配置创建TLS配置
+pub fn load(config: &ServerConfig) -> Result<RustlsConfig> {
+    Ok(RustlsConfig::from_config(Arc::new(build_server_config(config)?)))
+}
+
+/// 定期检查证书文件，修改后重新加载
+///
+/// 新证书加载失败时继续使用当前证书，已建立的连接不受影响。
+pub async fn watch(config: ServerConfig, tls: RustlsConfig) {
+    let interval = Duration::from_secs(config.tls_reload_interval_secs.max(1));
+    let mut modified = modified_times(&config);
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let current = modified_times(&config);
+        if current == modified {
+            continue;
+        }
+        modified = current;
+
+        match build_server_config(&config) {
+            Ok(server_config) => {
+                tls.reload_from_config(Arc::new(server_config));
+                log::info!("🔐 TLS certificates reloaded");
+            },
+            Err(e) => log::error!("Failed to reload TLS certificates, keeping previous ones: {:#}", e),
+        }
+    }
+}
+
+fn build_server_config(config: &ServerConfig) -> Result<rustls::ServerConfig> {
+    let cert_path = config.tls_cert_path.as_deref().context("server.tls_cert_path is not set")?;
+    let key_path = config.tls_key_path.as_deref().context("server.tls_key_path is not set")?;
+
+    let certs = load_certs(cert_path)?;
+    let key = load_key(key_path)?;
+
+    let provider = Arc::new(ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match config.tls_client_ca_path.as_deref() {
+        // 双向TLS，校验设备的客户端证书
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots.add(cert).with_context(|| format!("Invalid client CA certificate in {}", ca_path))?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if config.tls_client_auth_required {
+                verifier.build()?
+            } else {
+                verifier.allow_unauthenticated().build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        },
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder
+        .with_single_cert(certs, key)
+        .with_context(|| format!("Certificate {} does not match key {}", cert_path, key_path))?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(server_config)
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open certificate file {}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse certificates in {}", path))?;
+
+    if certs.is_empty() {
+        return Err(anyhow::anyhow!("No certificates found in {}", path));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open private key file {}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse private key in {}", path))?
+        .ok_or_else(|| anyhow::anyhow!("No private key found in {}", path))
+}
+
+/// 证书、私钥和客户端CA文件的修改时间
+fn modified_times(config: &ServerConfig) -> Vec<Option<SystemTime>> {
+    [&config.tls_cert_path, &config.tls_key_path, &config.tls_client_ca_path]
+        .into_iter()
+        .map(|path| {
+            path.as_deref()
+                .and_then(|path| fs::metadata(path).ok())
+                .and_then(|metadata| metadata.modified().ok())
+        })
+        .collect()
+}
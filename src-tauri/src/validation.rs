@@ -0,0 +1,320 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use crate::models::{DeviceSignalRequest, SignalValue};
+use crate::config::IngestConfig;
+
+/// `device_id`和`signal_type`的最大长度，与MariaDB列定义一致
+const MAX_ID_LENGTH: usize = 255;
+/// `unit`的最大长度，与MariaDB列定义一致
+const MAX_UNIT_LENGTH: usize = 50;
+/// 字符串和JSON值的最大字节数，与MariaDB `TEXT`列一致
+const MAX_TEXT_BYTES: usize = 65_535;
+/// 由网关写入的标签，请求中的`tags`不能使用
+const RESERVED_TAGS: [&str; 3] = ["device_id", "signal_type", "unit"];
+const CONTROL_MESSAGE: &str = "must not contain control characters";
+
+/// 字段级校验错误
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    /// 出错字段的路径，例如`value`、`fields.voltage`
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// 一个信号请求的全部校验错误
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{}", .0.iter().map(|e| format!("{}: {}", e.field, e.message)).collect::<Vec<_>>().join("; "))]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+/// 校验信号请求，返回信号时间
+///
+/// 设备未提供时间戳时使用`received_at`。
+pub fn validate_request(
+    request: &DeviceSignalRequest,
+    config: &IngestConfig,
+    received_at: DateTime<Utc>,
+) -> Result<DateTime<Utc>, ValidationErrors> {
+    let mut errors = Vec::new();
+
+    if let Some(id) = &request.id {
+        validate_identifier(&mut errors, "id", id);
+    }
+    validate_identifier(&mut errors, "device_id", &request.device_id);
+    validate_identifier(&mut errors, "signal_type", &request.signal_type);
+
+    if let Some(unit) = &request.unit {
+        if unit.chars().count() > MAX_UNIT_LENGTH {
+            errors.push(FieldError::new("unit", format!("must be at most {} characters", MAX_UNIT_LENGTH)));
+        } else if has_control(unit) {
+            errors.push(FieldError::new("unit", CONTROL_MESSAGE));
+        }
+    }
+
+    let fields = request.fields.as_ref().filter(|fields| !fields.is_empty());
+    match (&request.value, fields) {
+        (Some(_), Some(_)) => errors.push(FieldError::new("value", "'value' and 'fields' are mutually exclusive")),
+        (None, None) => errors.push(FieldError::new("value", "either 'value' or non-empty 'fields' is required")),
+        _ => {},
+    }
+
+    if let Some(value) = &request.value {
+        validate_value(&mut errors, "value", value);
+    }
+
+    if let Some(fields) = fields {
+        if fields.len() > config.max_fields {
+            errors.push(FieldError::new("fields", format!("must contain at most {} fields", config.max_fields)));
+        }
+        for (name, value) in fields {
+            if name.trim().is_empty() {
+                errors.push(FieldError::new("fields", "field names must not be empty"));
+            } else if has_control(name) {
+                errors.push(FieldError::new(format!("fields.{}", name.escape_debug()), "field names must not contain control characters"));
+            }
+            validate_value(&mut errors, &format!("fields.{}", name), value);
+        }
+    }
+
+    if let Some(tags) = &request.tags {
+        for (key, value) in tags {
+            if key.trim().is_empty() {
+                errors.push(FieldError::new("tags", "tag names must not be empty"));
+            } else if has_control(key) {
+                errors.push(FieldError::new(format!("tags.{}", key.escape_debug()), "tag names must not contain control characters"));
+            } else if RESERVED_TAGS.contains(&key.as_str()) {
+                errors.push(FieldError::new(format!("tags.{}", key), "reserved tag name"));
+            } else if value.chars().count() > MAX_ID_LENGTH {
+                errors.push(FieldError::new(
+                    format!("tags.{}", key),
+                    format!("must be at most {} characters", MAX_ID_LENGTH),
+                ));
+            } else if has_control(value) {
+                errors.push(FieldError::new(format!("tags.{}", key), CONTROL_MESSAGE));
+            }
+        }
+    }
+
+    if let Some(metadata) = &request.metadata {
+        let size = serde_json::to_vec(metadata).map(|bytes| bytes.len()).unwrap_or(usize::MAX);
+        if size > config.max_metadata_bytes {
+            errors.push(FieldError::new(
+                "metadata",
+                format!("is {} bytes, maximum is {}", size, config.max_metadata_bytes),
+            ));
+        }
+
+        // 顶层键可以通过`influxdb.tag_keys`/`field_keys`映射为标签或字段
+        if let serde_json::Value::Object(metadata) = metadata {
+            for key in metadata.keys().filter(|key| has_control(key)) {
+                errors.push(FieldError::new(
+                    format!("metadata.{}", key.escape_debug()),
+                    "metadata keys must not contain control characters",
+                ));
+            }
+        }
+    }
+
+    let timestamp = match &request.timestamp {
+        Some(timestamp) => match timestamp.parse(request.timestamp_unit.as_deref()) {
+            Ok(timestamp) => {
+                validate_timestamp(&mut errors, config, timestamp, received_at);
+                timestamp
+            },
+            Err(e) => {
+                errors.push(FieldError::new("timestamp", e.to_string()));
+                received_at
+            }
+        },
+        None => received_at,
+    };
+
+    if errors.is_empty() {
+        Ok(timestamp)
+    } else {
+        Err(ValidationErrors(errors))
+    }
+}
+
+fn validate_identifier(errors: &mut Vec<FieldError>, field: &str, value: &str) {
+    if value.trim().is_empty() {
+        errors.push(FieldError::new(field, "must not be empty"));
+    } else if value.chars().count() > MAX_ID_LENGTH {
+        errors.push(FieldError::new(field, format!("must be at most {} characters", MAX_ID_LENGTH)));
+    } else if has_control(value) {
+        errors.push(FieldError::new(field, CONTROL_MESSAGE));
+    }
+}
+
+/// 控制字符（例如换行）会破坏InfluxDB行协议和日志，标识符、标签和字段名中都不允许出现
+fn has_control(value: &str) -> bool {
+    value.chars().any(char::is_control)
+}
+
+fn validate_value(errors: &mut Vec<FieldError>, field: &str, value: &SignalValue) {
+    match value {
+        SignalValue::Float(number) if !number.is_finite() => {
+            errors.push(FieldError::new(field, "must be a finite number"));
+        },
+        SignalValue::String(_) | SignalValue::Json(_) => {
+            let size = value.as_text().map(|text| text.len()).unwrap_or(0);
+            if size > MAX_TEXT_BYTES {
+                errors.push(FieldError::new(field, format!("is {} bytes, maximum is {}", size, MAX_TEXT_BYTES)));
+            }
+        },
+        _ => {},
+    }
+}
+
+fn validate_timestamp(
+    errors: &mut Vec<FieldError>,
+    config: &IngestConfig,
+    timestamp: DateTime<Utc>,
+    received_at: DateTime<Utc>,
+) {
+    let skew = timestamp - received_at;
+    if skew > Duration::seconds(config.max_clock_skew_secs as i64) {
+        errors.push(FieldError::new(
+            "timestamp",
+            format!(
+                "{} is {}s ahead of gateway time (tolerance {}s)",
+                timestamp.to_rfc3339(), skew.num_seconds(), config.max_clock_skew_secs
+            ),
+        ));
+    }
+
+    if config.max_signal_age_secs > 0
+        && received_at - timestamp > Duration::seconds(config.max_signal_age_secs as i64)
+    {
+        errors.push(FieldError::new(
+            "timestamp",
+            format!(
+                "{} is older than the maximum signal age of {}s",
+                timestamp.to_rfc3339(), config.max_signal_age_secs
+            ),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn config() -> IngestConfig {
+        IngestConfig {
+            max_clock_skew_secs: 60,
+            max_signal_age_secs: 3600,
+            max_metadata_bytes: 1024,
+            max_fields: 3,
+            max_batch_size: 100,
+            max_body_bytes: 1024 * 1024,
+            dedup_window_secs: 600,
+            dedup_capacity: 1000,
+        }
+    }
+
+    fn received_at() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap()
+    }
+
+    /// 在合法请求上覆盖指定字段
+    fn request(overrides: serde_json::Value) -> DeviceSignalRequest {
+        let mut request = json!({"device_id": "pump-1", "signal_type": "temperature", "value": 21.5});
+        for (key, value) in overrides.as_object().unwrap() {
+            request[key] = value.clone();
+        }
+        serde_json::from_value(request).unwrap()
+    }
+
+    /// 校验失败的字段路径
+    fn failed_fields(overrides: serde_json::Value) -> Vec<String> {
+        match validate_request(&request(overrides), &config(), received_at()) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.0.into_iter().map(|e| e.field).collect(),
+        }
+    }
+
+    fn timestamp_offset(secs: i64) -> serde_json::Value {
+        json!((received_at() + Duration::seconds(secs)).to_rfc3339())
+    }
+
+    #[test]
+    fn accepts_valid_request() {
+        assert!(failed_fields(json!({})).is_empty());
+        assert!(failed_fields(json!({"value": null, "fields": {"voltage": 230.1, "current": 5.2}})).is_empty());
+    }
+
+    #[test]
+    fn requires_exactly_one_of_value_and_fields() {
+        assert_eq!(failed_fields(json!({"fields": {"voltage": 230.1}})), vec!["value"]);
+        assert_eq!(failed_fields(json!({"value": null})), vec!["value"]);
+        assert_eq!(failed_fields(json!({"value": null, "fields": {}})), vec!["value"]);
+    }
+
+    #[test]
+    fn rejects_reserved_tag_names() {
+        for tag in RESERVED_TAGS {
+            assert_eq!(failed_fields(json!({"tags": {tag: "x"}})), vec![format!("tags.{}", tag)]);
+        }
+        assert!(failed_fields(json!({"tags": {"site": "north"}})).is_empty());
+    }
+
+    #[test]
+    fn rejects_control_characters() {
+        assert_eq!(failed_fields(json!({"id": "order\n42"})), vec!["id"]);
+        assert_eq!(failed_fields(json!({"device_id": "pump\t1"})), vec!["device_id"]);
+        assert_eq!(failed_fields(json!({"unit": "°C\r"})), vec!["unit"]);
+        assert_eq!(failed_fields(json!({"tags": {"site\n": "north"}})), vec!["tags.site\\n"]);
+        assert_eq!(failed_fields(json!({"tags": {"site": "north\u{0}"}})), vec!["tags.site"]);
+        assert_eq!(failed_fields(json!({"value": null, "fields": {"volt\nage": 230.1}})), vec!["fields.volt\\nage"]);
+        assert_eq!(failed_fields(json!({"metadata": {"fw\u{1b}": "1.2"}})), vec!["metadata.fw\\u{1b}"]);
+    }
+
+    #[test]
+    fn enforces_length_limits() {
+        let id = "a".repeat(MAX_ID_LENGTH);
+        assert!(failed_fields(json!({"device_id": id})).is_empty());
+        assert_eq!(failed_fields(json!({"device_id": format!("{}a", id)})), vec!["device_id"]);
+        assert_eq!(failed_fields(json!({"signal_type": format!("{}a", id)})), vec!["signal_type"]);
+        assert_eq!(failed_fields(json!({"tags": {"site": format!("{}a", id)}})), vec!["tags.site"]);
+        assert_eq!(failed_fields(json!({"unit": "u".repeat(MAX_UNIT_LENGTH + 1)})), vec!["unit"]);
+        assert_eq!(failed_fields(json!({"value": "x".repeat(MAX_TEXT_BYTES + 1)})), vec!["value"]);
+        assert_eq!(failed_fields(json!({"metadata": {"note": "x".repeat(1024)}})), vec!["metadata"]);
+        assert_eq!(
+            failed_fields(json!({"value": null, "fields": {"a": 1, "b": 2, "c": 3, "d": 4}})),
+            vec!["fields"]
+        );
+    }
+
+    #[test]
+    fn enforces_clock_skew_boundary() {
+        assert!(failed_fields(json!({"timestamp": timestamp_offset(60)})).is_empty());
+        assert_eq!(failed_fields(json!({"timestamp": timestamp_offset(61)})), vec!["timestamp"]);
+    }
+
+    #[test]
+    fn enforces_maximum_age_boundary() {
+        assert!(failed_fields(json!({"timestamp": timestamp_offset(-3600)})).is_empty());
+        assert_eq!(failed_fields(json!({"timestamp": timestamp_offset(-3601)})), vec!["timestamp"]);
+
+        // 0表示不限制
+        let config = IngestConfig { max_signal_age_secs: 0, ..config() };
+        let old = request(json!({"timestamp": timestamp_offset(-365 * 24 * 3600)}));
+        assert!(validate_request(&old, &config, received_at()).is_ok());
+    }
+
+    #[test]
+    fn uses_received_time_without_timestamp() {
+        assert_eq!(validate_request(&request(json!({})), &config(), received_at()).unwrap(), received_at());
+    }
+}